@@ -0,0 +1,276 @@
+use error_stack::{Result, ResultExt};
+use kyoka::perform_request;
+use kyoka::util::SessionLimiter;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::metrics::Metrics;
+use crate::queue::BucketQueue;
+use crate::{config, SetupError};
+
+/// Label of the application used if no bot tokens are configured
+const FALLBACK_LABEL: &str = "default";
+
+#[derive(Debug, Error)]
+pub enum LookupError {
+    #[error("Application id is required if multiple bots use this queue")]
+    Missing,
+    #[error("Application is not served by this queue")]
+    Unknown,
+}
+
+/// Identify queue and session start limit of one bot
+#[derive(Debug)]
+pub struct Application {
+    id: Option<u64>,
+    limiter: SessionLimiter,
+    queue: Arc<BucketQueue>,
+    recommended_shards: Option<u64>,
+}
+
+impl Application {
+    /// Gets the application and its maximum concurrency
+    /// from Discord with the bot token.
+    async fn connect(
+        token: &str,
+        cfg: &config::Server,
+        metrics: &Metrics,
+    ) -> Result<Self, SetupError> {
+        let mut http = twilight_http::Client::builder().token(token.into());
+        if let Some(proxy_url) = cfg.proxy_url() {
+            http = http.proxy(proxy_url.into(), cfg.proxy_use_http());
+        }
+
+        let http = http.build();
+        let info =
+            perform_request!(http.current_user_application(), SetupError)
+                .await?;
+
+        let gateway =
+            perform_request!(http.gateway().authed(), SetupError).await?;
+
+        let id = info.id.get();
+        let label = id.to_string();
+        let limit = gateway.session_start_limit;
+
+        tracing::info!(
+            application.id = %id,
+            application.name = %info.name,
+            max_concurrency = %limit.max_concurrency,
+            remaining = %limit.remaining,
+            total = %limit.total,
+            reset_after = ?Duration::from_millis(limit.reset_after),
+            floor = %cfg.session_limit().floor(),
+            "Received session start limit"
+        );
+
+        let limiter = SessionLimiter::new(&limit);
+        record_session_starts(metrics, &label, &limiter);
+
+        let queue = Arc::new(BucketQueue::new(
+            label.clone(),
+            limit.max_concurrency,
+            metrics.clone(),
+        ));
+
+        tokio::spawn(refresh_session_limit(
+            http,
+            label,
+            limiter.clone(),
+            queue.clone(),
+            metrics.clone(),
+            cfg.session_limit().refresh_interval(),
+        ));
+
+        Ok(Self {
+            id: Some(id),
+            limiter,
+            queue,
+            recommended_shards: Some(gateway.shards),
+        })
+    }
+
+    fn fallback(metrics: &Metrics) -> Self {
+        Self {
+            id: None,
+            limiter: SessionLimiter::default(),
+            queue: Arc::new(BucketQueue::new(
+                FALLBACK_LABEL.into(),
+                1,
+                metrics.clone(),
+            )),
+            recommended_shards: None,
+        }
+    }
+
+    /// Application id, which is unknown if no bot tokens are configured
+    #[must_use]
+    pub const fn id(&self) -> Option<u64> {
+        self.id
+    }
+
+    #[must_use]
+    pub const fn limiter(&self) -> &SessionLimiter {
+        &self.limiter
+    }
+
+    #[must_use]
+    pub fn queue(&self) -> &BucketQueue {
+        &self.queue
+    }
+
+    /// Label of the application in metrics
+    #[must_use]
+    pub fn label(&self) -> String {
+        self.id.map_or_else(|| FALLBACK_LABEL.into(), |id| id.to_string())
+    }
+
+    /// Updates the remaining session starts in metrics, which has to
+    /// be done whenever a session start is taken or given back.
+    pub fn record_session_starts(&self, metrics: &Metrics) {
+        record_session_starts(metrics, &self.label(), &self.limiter);
+    }
+}
+
+/// Sets the remaining session starts in metrics to the tracked
+/// limit, which also counts session starts taken by this queue.
+fn record_session_starts(
+    metrics: &Metrics,
+    label: &str,
+    limiter: &SessionLimiter,
+) {
+    let Some(report) = limiter.report() else { return };
+    metrics
+        .session_starts_remaining()
+        .with_label_values(&[label])
+        .set(i64::try_from(report.remaining).unwrap_or(i64::MAX));
+}
+
+/// Keeps the session start limit up to date, since shards may
+/// also identify without going through this queue.
+///
+/// The buckets of the queue are rebuilt as well whenever Discord
+/// changes the maximum concurrency of the bot.
+async fn refresh_session_limit(
+    http: twilight_http::Client,
+    label: String,
+    limiter: SessionLimiter,
+    queue: Arc<BucketQueue>,
+    metrics: Metrics,
+    period: Duration,
+) {
+    let mut interval =
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+        match limiter.refresh(&http).await {
+            Ok(limit) => {
+                tracing::info!(
+                    application.id = %label,
+                    remaining = %limit.remaining,
+                    total = %limit.total,
+                    reset_after = ?Duration::from_millis(limit.reset_after),
+                    "Refreshed session start limit"
+                );
+                record_session_starts(&metrics, &label, &limiter);
+
+                let previous = queue.max_concurrency();
+                if limit.max_concurrency != previous {
+                    tracing::info!(
+                        application.id = %label,
+                        %previous,
+                        max_concurrency = %limit.max_concurrency,
+                        "Maximum concurrency changed, rebuilding buckets"
+                    );
+                    queue.set_max_concurrency(limit.max_concurrency);
+                }
+            },
+            Err(error) => {
+                tracing::warn!(
+                    ?error,
+                    application.id = %label,
+                    "Failed to refresh session start limit"
+                );
+            },
+        }
+    }
+}
+
+/// Every application served by the queue, keyed by application id.
+///
+/// A single application with unknown id and concurrency is used
+/// for all requests if no bot tokens are configured.
+#[derive(Debug)]
+pub struct Applications {
+    by_id: HashMap<u64, Application>,
+    fallback: Option<Application>,
+}
+
+impl Applications {
+    pub async fn from_config(
+        cfg: &config::Server,
+        metrics: &Metrics,
+    ) -> Result<Self, SetupError> {
+        if cfg.tokens().is_empty() {
+            tracing::info!(
+                "No bot tokens are present, using a single bucket for all bots"
+            );
+            let fallback = Some(Application::fallback(metrics));
+            return Ok(Self { by_id: HashMap::new(), fallback });
+        }
+
+        let mut by_id = HashMap::new();
+        for token in cfg.tokens() {
+            let application =
+                Application::connect(token, cfg, metrics)
+                    .await
+                    .attach_printable("Failed to set up a bot application")?;
+
+            let id = application.id.expect("application id should be known");
+            if by_id.insert(id, application).is_some() {
+                tracing::warn!(
+                    application.id = %id,
+                    "Multiple bot tokens of the same application are present"
+                );
+            }
+        }
+
+        Ok(Self { by_id, fallback: None })
+    }
+
+    /// Finds the application of a request. The application id can
+    /// be left out if there's only one application.
+    pub fn get(
+        &self,
+        id: Option<u64>,
+    ) -> std::result::Result<&Application, LookupError> {
+        if let Some(fallback) = &self.fallback {
+            return Ok(fallback);
+        }
+
+        match id {
+            Some(id) => self.by_id.get(&id).ok_or(LookupError::Unknown),
+            None if self.by_id.len() == 1 => {
+                Ok(self.by_id.values().next().expect("one application"))
+            },
+            None => Err(LookupError::Missing),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Application> {
+        self.by_id.values().chain(self.fallback.as_ref())
+    }
+
+    /// Discord's recommended amount of shards, if only one
+    /// application is served by the queue.
+    #[must_use]
+    pub fn recommended_shards(&self) -> Option<u64> {
+        match self.by_id.len() {
+            1 => self.by_id.values().next()?.recommended_shards,
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use error_stack::{Result, ResultExt};
+use kyoka::config::LoadError;
+use kyoka::util::env;
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct Coordinator {
+    heartbeat_timeout: Duration,
+    shard_total: Option<NonZeroU64>,
+    shards_per_process: NonZeroU64,
+}
+
+const DEFAULT_HEARTBEAT_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_SHARDS_PER_PROCESS: u64 = 16;
+
+impl Coordinator {
+    pub fn is_enabled() -> bool {
+        env::var_parse::<bool>("COORDINATOR_ENABLED")
+            .ok()
+            .and_then(|v| v)
+            .unwrap_or_default()
+    }
+
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let heartbeat_timeout = env::var_parse("COORDINATOR_HEARTBEAT_TIMEOUT")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_HEARTBEAT_TIMEOUT_SECS);
+
+        let shard_total = env::var_parse("COORDINATOR_SHARD_TOTAL")
+            .change_context(LoadError)?;
+
+        let shards_per_process =
+            env::var_parse("COORDINATOR_SHARDS_PER_PROCESS")
+                .change_context(LoadError)?
+                .unwrap_or(
+                    NonZeroU64::new(DEFAULT_SHARDS_PER_PROCESS).unwrap(),
+                );
+
+        Ok(Self {
+            heartbeat_timeout: Duration::from_secs(heartbeat_timeout.max(1)),
+            shard_total,
+            shards_per_process,
+        })
+    }
+}
+
+impl Coordinator {
+    /// How long a shard process can go without sending heartbeats
+    /// before its shard range is given to another process
+    #[must_use]
+    pub const fn heartbeat_timeout(&self) -> Duration {
+        self.heartbeat_timeout
+    }
+
+    /// Total amount of shards to split into ranges.
+    ///
+    /// Discord's recommended amount of shards is used if it is not
+    /// set, which requires `DISCORD_BOT_TOKEN` to be set.
+    #[must_use]
+    pub const fn shard_total(&self) -> Option<NonZeroU64> {
+        self.shard_total
+    }
+
+    /// How many shards are assigned to each shard process
+    #[must_use]
+    pub const fn shards_per_process(&self) -> NonZeroU64 {
+        self.shards_per_process
+    }
+}
@@ -1,4 +1,6 @@
+mod coordinator;
 mod server;
+pub use coordinator::Coordinator;
 pub use server::Server;
 
 pub use kyoka::config::*;
@@ -5,9 +5,12 @@ use std::net::{IpAddr, Ipv4Addr};
 
 #[derive(Debug)]
 pub struct Server {
+    coordinator: Option<super::Coordinator>,
     host: IpAddr,
     port: u16,
-    token: Option<Sensitive<String>>,
+    secret: Option<Sensitive<String>>,
+    session_limit: super::SessionLimit,
+    tokens: Vec<Sensitive<String>>,
     proxy_url: Option<String>,
     proxy_use_http: bool,
 }
@@ -26,10 +29,30 @@ impl Server {
             .change_context(LoadError)?
             .unwrap_or(DEFAULT_PORT);
 
-        let token = env::var("DISCORD_BOT_TOKEN")
+        let secret = env::var("GATEWAY_QUEUE_SECRET")
             .change_context(LoadError)?
+            .filter(|v| !v.is_empty())
             .map(Sensitive::new);
 
+        // Tokens of every bot using this queue, each identified
+        // by its own application id
+        let mut tokens = env::var("DISCORD_BOT_TOKEN")
+            .change_context(LoadError)?
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        if let Some(list) =
+            env::var("DISCORD_BOT_TOKENS").change_context(LoadError)?
+        {
+            tokens.extend(list.split(',').map(|v| v.trim().to_string()));
+        }
+
+        let tokens = tokens
+            .into_iter()
+            .filter(|v| !v.is_empty())
+            .map(Sensitive::new)
+            .collect();
+
         let proxy_url = env::var("BOT_PROXY_URL").change_context(LoadError)?;
         let proxy_use_http = match &proxy_url {
             Some(..) => env::var_parse("BOT_PROXY_USE_HTTP")
@@ -38,11 +61,32 @@ impl Server {
             None => false,
         };
 
-        Ok(Self { host, port, token, proxy_url, proxy_use_http })
+        let coordinator = if super::Coordinator::is_enabled() {
+            Some(super::Coordinator::from_env()?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            coordinator,
+            host,
+            port,
+            secret,
+            session_limit: super::SessionLimit::from_env()?,
+            tokens,
+            proxy_url,
+            proxy_use_http,
+        })
     }
 }
 
 impl Server {
+    /// Coordinator settings, if the coordinator role is enabled
+    #[must_use]
+    pub const fn coordinator(&self) -> Option<&super::Coordinator> {
+        self.coordinator.as_ref()
+    }
+
     #[must_use]
     pub const fn host(&self) -> IpAddr {
         self.host
@@ -53,9 +97,23 @@ impl Server {
         self.port
     }
 
+    /// Shared secret every shard process has to send as a bearer
+    /// token. Anyone can use the queue if it is not set.
+    #[must_use]
+    pub const fn secret(&self) -> Option<&Sensitive<String>> {
+        self.secret.as_ref()
+    }
+
+    #[must_use]
+    pub const fn session_limit(&self) -> &super::SessionLimit {
+        &self.session_limit
+    }
+
+    /// Bot tokens from `DISCORD_BOT_TOKEN` and the comma-separated
+    /// `DISCORD_BOT_TOKENS`, one for each application using the queue
     #[must_use]
-    pub fn token(&self) -> Option<&str> {
-        self.token.as_deref()
+    pub fn tokens(&self) -> &[Sensitive<String>] {
+        &self.tokens
     }
 
     #[must_use]
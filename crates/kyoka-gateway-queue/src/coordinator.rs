@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Shard range given to a registered shard process
+#[derive(Debug, Clone, Serialize)]
+pub struct Assignment {
+    pub process: String,
+    pub id: u64,
+    pub amount: u64,
+    pub total: u64,
+    pub heartbeat_interval_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatRequest {
+    pub process: String,
+    pub name: String,
+    pub id: u64,
+    pub amount: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heartbeat {
+    Accepted,
+    /// The shard range belongs to another process now, so
+    /// the process has to stop its shards.
+    Reassigned,
+}
+
+#[derive(Debug)]
+struct Owner {
+    process: String,
+    name: String,
+    last_seen: Instant,
+}
+
+#[derive(Debug)]
+struct Range {
+    id: u64,
+    amount: u64,
+    owner: Option<Owner>,
+}
+
+/// Splits shards into ranges and assigns them to shard
+/// processes which are registered to the coordinator.
+#[derive(Debug)]
+pub struct Coordinator {
+    heartbeat_timeout: Duration,
+    next_process: AtomicU64,
+    ranges: Mutex<Vec<Range>>,
+    /// Distinguishes process ids given before the coordinator restarts
+    started_at: u128,
+    total: u64,
+}
+
+impl Coordinator {
+    #[must_use]
+    pub fn new(
+        total: u64,
+        per_process: u64,
+        heartbeat_timeout: Duration,
+    ) -> Self {
+        let ranges = (0..total)
+            .step_by(per_process as usize)
+            .map(|id| Range {
+                id,
+                amount: per_process.min(total - id),
+                owner: None,
+            })
+            .collect();
+
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        Self {
+            heartbeat_timeout,
+            next_process: AtomicU64::new(0),
+            ranges: Mutex::new(ranges),
+            started_at,
+            total,
+        }
+    }
+
+    fn is_alive(&self, owner: &Owner) -> bool {
+        owner.last_seen.elapsed() <= self.heartbeat_timeout
+    }
+
+    fn assignment(&self, range: &Range, process: String) -> Assignment {
+        Assignment {
+            process,
+            id: range.id,
+            amount: range.amount,
+            total: self.total,
+            heartbeat_interval_secs: (self.heartbeat_timeout.as_secs() / 3)
+                .max(1),
+        }
+    }
+
+    /// Assigns the first shard range not owned by any living
+    /// process, or returns `None` if every range is taken.
+    pub fn register(&self, name: String) -> Option<Assignment> {
+        let mut ranges = self.ranges.lock().expect("ranges lock poisoned");
+        let range = ranges.iter_mut().find(|range| {
+            range.owner.as_ref().map_or(true, |owner| !self.is_alive(owner))
+        })?;
+
+        if let Some(previous) = &range.owner {
+            tracing::warn!(
+                previous.process = %previous.process,
+                previous.name = %previous.name,
+                range.id = %range.id,
+                "Reassigning shard range of a dead process"
+            );
+        }
+
+        let number = self.next_process.fetch_add(1, Ordering::Relaxed);
+        let process = format!("{:x}-{number}", self.started_at);
+        tracing::info!(
+            process = %process,
+            name = %name,
+            range.id = %range.id,
+            range.amount = %range.amount,
+            "Registered shard process"
+        );
+
+        range.owner = Some(Owner {
+            process: process.clone(),
+            name,
+            last_seen: Instant::now(),
+        });
+
+        Some(self.assignment(range, process))
+    }
+
+    /// Keeps the shard range assigned to the process.
+    ///
+    /// Processes registered before the coordinator restarts can
+    /// take their shard range back if nobody has taken it yet.
+    pub fn heartbeat(&self, request: HeartbeatRequest) -> Heartbeat {
+        let mut ranges = self.ranges.lock().expect("ranges lock poisoned");
+        let Some(range) = ranges.iter_mut().find(|range| {
+            range.id == request.id
+                && range.amount == request.amount
+                && self.total == request.total
+        }) else {
+            return Heartbeat::Reassigned;
+        };
+
+        match &mut range.owner {
+            Some(owner) if owner.process == request.process => {
+                owner.last_seen = Instant::now();
+                Heartbeat::Accepted
+            },
+            Some(owner) if self.is_alive(owner) => Heartbeat::Reassigned,
+            _ => {
+                tracing::info!(
+                    process = %request.process,
+                    name = %request.name,
+                    range.id = %range.id,
+                    "Shard process has taken its shard range back"
+                );
+                range.owner = Some(Owner {
+                    process: request.process,
+                    name: request.name,
+                    last_seen: Instant::now(),
+                });
+                Heartbeat::Accepted
+            },
+        }
+    }
+
+    /// Releases the shard range of the process, usually
+    /// because it is shutting down.
+    pub fn unregister(&self, process: &str) {
+        let mut ranges = self.ranges.lock().expect("ranges lock poisoned");
+        for range in ranges.iter_mut() {
+            if range.owner.as_ref().is_some_and(|v| v.process == process) {
+                tracing::info!(
+                    process = %process,
+                    range.id = %range.id,
+                    "Shard process has released its shard range"
+                );
+                range.owner = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Coordinator, Heartbeat, HeartbeatRequest};
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn heartbeat(process: &str, id: u64, amount: u64) -> HeartbeatRequest {
+        HeartbeatRequest {
+            process: process.to_string(),
+            name: "test".to_string(),
+            id,
+            amount,
+            total: 5,
+        }
+    }
+
+    fn expire() {
+        std::thread::sleep(TIMEOUT * 2);
+    }
+
+    #[test]
+    fn test_register() {
+        let coordinator = Coordinator::new(5, 2, TIMEOUT);
+
+        let first = coordinator.register("a".into()).unwrap();
+        let second = coordinator.register("b".into()).unwrap();
+        let third = coordinator.register("c".into()).unwrap();
+        assert_eq!((first.id, first.amount, first.total), (0, 2, 5));
+        assert_eq!((second.id, second.amount), (2, 2));
+        assert_eq!((third.id, third.amount), (4, 1));
+        assert_ne!(first.process, second.process);
+
+        assert!(coordinator.register("d".into()).is_none());
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let coordinator = Coordinator::new(5, 2, TIMEOUT);
+        let first = coordinator.register("a".into()).unwrap();
+
+        let request = heartbeat(&first.process, first.id, first.amount);
+        assert_eq!(coordinator.heartbeat(request), Heartbeat::Accepted);
+
+        // Somebody else claiming a range owned by a living process
+        let request = heartbeat("other", first.id, first.amount);
+        assert_eq!(coordinator.heartbeat(request), Heartbeat::Reassigned);
+
+        // Ranges that don't exist
+        let request = heartbeat(&first.process, 1, 2);
+        assert_eq!(coordinator.heartbeat(request), Heartbeat::Reassigned);
+    }
+
+    #[test]
+    fn test_heartbeat_keeps_range_alive() {
+        let coordinator = Coordinator::new(2, 2, TIMEOUT);
+        let first = coordinator.register("a".into()).unwrap();
+
+        for _ in 0..4 {
+            std::thread::sleep(TIMEOUT / 2);
+            let request = heartbeat(&first.process, first.id, first.amount);
+            assert_eq!(coordinator.heartbeat(request), Heartbeat::Accepted);
+        }
+
+        assert!(coordinator.register("b".into()).is_none());
+    }
+
+    #[test]
+    fn test_expired_range_is_reassigned() {
+        let coordinator = Coordinator::new(2, 2, TIMEOUT);
+        let first = coordinator.register("a".into()).unwrap();
+
+        expire();
+        let second = coordinator.register("b".into()).unwrap();
+        assert_eq!(second.id, first.id);
+        assert_ne!(second.process, first.process);
+
+        let request = heartbeat(&first.process, first.id, first.amount);
+        assert_eq!(coordinator.heartbeat(request), Heartbeat::Reassigned);
+
+        let request = heartbeat(&second.process, second.id, second.amount);
+        assert_eq!(coordinator.heartbeat(request), Heartbeat::Accepted);
+    }
+
+    #[test]
+    fn test_expired_range_is_taken_back() {
+        let coordinator = Coordinator::new(2, 2, TIMEOUT);
+        let first = coordinator.register("a".into()).unwrap();
+
+        // Nobody has taken the range while the process was gone
+        expire();
+        let request = heartbeat(&first.process, first.id, first.amount);
+        assert_eq!(coordinator.heartbeat(request), Heartbeat::Accepted);
+        assert!(coordinator.register("b".into()).is_none());
+    }
+
+    #[test]
+    fn test_unregister() {
+        let coordinator = Coordinator::new(2, 2, TIMEOUT);
+        let first = coordinator.register("a".into()).unwrap();
+
+        coordinator.unregister(&first.process);
+        let second = coordinator.register("b".into()).unwrap();
+        assert_eq!(second.id, first.id);
+    }
+}
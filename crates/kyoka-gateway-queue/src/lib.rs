@@ -1,4 +1,8 @@
+pub mod application;
 pub mod config;
+pub mod coordinator;
+pub mod metrics;
+pub mod queue;
 pub mod server;
 
 use thiserror::Error;
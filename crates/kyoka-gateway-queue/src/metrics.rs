@@ -0,0 +1,36 @@
+use prometheus::{HistogramVec, IntCounterVec, IntGaugeVec};
+use prometheus_macros::composite_metric;
+
+composite_metric! {
+    #[derive(Debug, Clone)]
+    pub struct Metrics {
+        #[name = "auth_failures"]
+        #[desc = "Requests rejected for a missing or invalid secret"]
+        #[labels = ["reason"]]
+        auth_failures: IntCounterVec,
+        #[name = "waiting_requests"]
+        #[desc = "Requests waiting for their turn to identify in each bucket"]
+        #[labels = ["application", "bucket"]]
+        waiting_requests: IntGaugeVec,
+        #[name = "grant_latency"]
+        #[desc = "Time taken from requesting until allowed to identify in seconds"]
+        #[labels = ["application", "bucket"]]
+        grant_latency: HistogramVec,
+        #[name = "grants"]
+        #[desc = "Identifies allowed in each bucket"]
+        #[labels = ["application", "bucket"]]
+        grants: IntCounterVec,
+        #[name = "cancelled_requests"]
+        #[desc = "Requests gone before their turn to identify"]
+        #[labels = ["application"]]
+        cancelled_requests: IntCounterVec,
+        #[name = "max_concurrency"]
+        #[desc = "Maximum concurrent identifies allowed by Discord"]
+        #[labels = ["application"]]
+        max_concurrency: IntGaugeVec,
+        #[name = "session_starts_remaining"]
+        #[desc = "Remaining session starts allowed by Discord until reset"]
+        #[labels = ["application"]]
+        session_starts_remaining: IntGaugeVec,
+    }
+}
@@ -0,0 +1,905 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::{oneshot, Notify};
+use tokio::task::JoinHandle;
+
+use crate::metrics::Metrics;
+
+/// Time between identifies in the same bucket.
+///
+/// Discord allows one identify every 5 seconds per bucket, the
+/// extra second covers the latency between granting and identifying.
+pub const IDENTIFY_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Amount of recent grants kept for the queue status
+const RECENT_GRANTS: usize = 20;
+
+/// How long the turn of a timed out request is held for it to
+/// ask again with its ticket, before moving on to the next request
+const TICKET_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    shard: u64,
+    total: u64,
+    grant: oneshot::Sender<()>,
+    queued_at: Instant,
+    /// Whether the request timed out and may ask again with its ticket
+    kept: bool,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    waiters: Mutex<VecDeque<Waiter>>,
+    /// Timed out request whose turn has come, waiting to be claimed
+    reserved: Mutex<Option<Waiter>>,
+    last_grant: Mutex<Option<Instant>>,
+    notify: Notify,
+}
+
+impl Bucket {
+    /// Time until the bucket can grant its next identify
+    fn ready_in(&self) -> Duration {
+        let last_grant = *self.last_grant.lock().expect("grant lock poisoned");
+        last_grant.map_or(Duration::ZERO, |granted_at| {
+            IDENTIFY_INTERVAL.saturating_sub(granted_at.elapsed())
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Grant {
+    shard: u64,
+    total: u64,
+    bucket: u64,
+    waited: Duration,
+    granted_at: Instant,
+}
+
+type RecentGrants = Arc<Mutex<VecDeque<Grant>>>;
+
+/// Shard waiting for its turn to identify, with `position` starting
+/// at 1 for the shard identifying next in its bucket.
+#[derive(Debug, Serialize)]
+pub struct WaiterStatus {
+    pub shard: u64,
+    pub total: u64,
+    pub position: usize,
+    pub waited_secs: u64,
+    pub estimated_grant_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketStatus {
+    pub bucket: u64,
+    pub waiting: Vec<WaiterStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GrantStatus {
+    pub shard: u64,
+    pub total: u64,
+    pub bucket: u64,
+    pub waited_secs: u64,
+    pub granted_secs_ago: u64,
+}
+
+/// Snapshot of the queue, with the most recent grants first
+#[derive(Debug, Serialize)]
+pub struct QueueStatus {
+    pub max_concurrency: u64,
+    pub buckets: Vec<BucketStatus>,
+    pub recent_grants: Vec<GrantStatus>,
+}
+
+#[derive(Debug, Error)]
+#[error("Gateway queue is closed")]
+pub struct QueueClosed;
+
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error(transparent)]
+    Closed(#[from] QueueClosed),
+    /// The request keeps its place in the bucket, which can be
+    /// taken over by requesting again with this ticket.
+    #[error("Gave up waiting for the turn to identify")]
+    TimedOut(u64),
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: Vec<Arc<Bucket>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Buckets {
+    /// Spawns the task of every bucket, which hold off their first
+    /// grant until `IDENTIFY_INTERVAL` has passed since `last_grant`.
+    fn spawn(
+        application: &str,
+        max_concurrency: u64,
+        metrics: &Metrics,
+        recent_grants: &RecentGrants,
+        last_grant: Option<Instant>,
+    ) -> Self {
+        let buckets = (0..max_concurrency.max(1))
+            .map(|_| {
+                Arc::new(Bucket {
+                    last_grant: Mutex::new(last_grant),
+                    ..Bucket::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let tasks = buckets
+            .iter()
+            .zip(0..)
+            .map(|(bucket, id)| {
+                tokio::spawn(run_bucket(
+                    application.to_string(),
+                    id,
+                    bucket.clone(),
+                    metrics.clone(),
+                    recent_grants.clone(),
+                ))
+            })
+            .collect();
+
+        metrics
+            .max_concurrency()
+            .with_label_values(&[application])
+            .set(i64::try_from(buckets.len()).unwrap_or(i64::MAX));
+
+        Self { buckets, tasks }
+    }
+
+    fn get(&self, shard: u64) -> (u64, &Bucket) {
+        let id = shard % self.buckets.len() as u64;
+        (id, &self.buckets[id as usize])
+    }
+}
+
+/// Identify queue which splits shards into `max_concurrency`
+/// buckets like Discord does.
+///
+/// Every bucket grants one identify at a time on its own, so
+/// shards from different buckets can identify in parallel.
+#[derive(Debug)]
+pub struct BucketQueue {
+    application: String,
+    closed: AtomicBool,
+    metrics: Metrics,
+    next_ticket: AtomicU64,
+    recent_grants: RecentGrants,
+    state: RwLock<Buckets>,
+}
+
+impl BucketQueue {
+    /// Creates a queue for the application, which is only used
+    /// to label the metrics.
+    #[must_use]
+    pub fn new(
+        application: String,
+        max_concurrency: u64,
+        metrics: Metrics,
+    ) -> Self {
+        let recent_grants = RecentGrants::default();
+        let state = Buckets::spawn(
+            &application,
+            max_concurrency,
+            &metrics,
+            &recent_grants,
+            None,
+        );
+
+        Self {
+            application,
+            closed: AtomicBool::new(false),
+            metrics,
+            next_ticket: AtomicU64::new(0),
+            recent_grants,
+            state: RwLock::new(state),
+        }
+    }
+
+    #[must_use]
+    pub fn max_concurrency(&self) -> u64 {
+        self.state.read().expect("buckets lock poisoned").buckets.len() as u64
+    }
+
+    /// Bucket where the shard identifies in, as `shard_id % max_concurrency`
+    #[must_use]
+    pub fn bucket(&self, shard: u64) -> u64 {
+        shard % self.max_concurrency()
+    }
+
+    /// Rebuilds the buckets for another maximum concurrency.
+    ///
+    /// Waiting requests keep their order and move to the bucket
+    /// of their shard, with no grant until `IDENTIFY_INTERVAL` has
+    /// passed since the last grant of any previous bucket.
+    pub fn set_max_concurrency(&self, max_concurrency: u64) {
+        let max_concurrency = max_concurrency.max(1);
+        let mut state = self.state.write().expect("buckets lock poisoned");
+        if state.buckets.len() as u64 == max_concurrency {
+            return;
+        }
+
+        let mut last_grant = None;
+        let mut waiters = Vec::new();
+        for (bucket, id) in state.buckets.iter().zip(0..) {
+            let granted_at =
+                *bucket.last_grant.lock().expect("grant lock poisoned");
+            last_grant = last_grant.max(granted_at);
+
+            waiters.extend(
+                bucket.reserved.lock().expect("reserved lock poisoned").take(),
+            );
+            waiters.extend(
+                bucket.waiters.lock().expect("waiters lock poisoned").drain(..),
+            );
+            self.set_waiting(id, 0);
+        }
+
+        for task in &state.tasks {
+            task.abort();
+        }
+
+        *state = Buckets::spawn(
+            &self.application,
+            max_concurrency,
+            &self.metrics,
+            &self.recent_grants,
+            last_grant,
+        );
+
+        waiters.sort_by_key(|waiter| waiter.ticket);
+        for waiter in waiters {
+            let (id, bucket) = state.get(waiter.shard);
+            self.metrics
+                .waiting_requests()
+                .with_label_values(&[&self.application, &id.to_string()])
+                .inc();
+            bucket
+                .waiters
+                .lock()
+                .expect("waiters lock poisoned")
+                .push_back(waiter);
+        }
+
+        for bucket in &state.buckets {
+            bucket.notify.notify_one();
+        }
+    }
+
+    /// Stops granting identifies, failing every waiting and
+    /// future request with [`QueueClosed`].
+    pub fn close(&self) {
+        let state = self.state.write().expect("buckets lock poisoned");
+        self.closed.store(true, Ordering::SeqCst);
+
+        for (bucket, id) in state.buckets.iter().zip(0..) {
+            // Dropping the waiters wakes up their requests
+            bucket.waiters.lock().expect("waiters lock poisoned").clear();
+            bucket.reserved.lock().expect("reserved lock poisoned").take();
+            self.set_waiting(id, 0);
+        }
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn set_waiting(&self, bucket: u64, amount: i64) {
+        self.metrics
+            .waiting_requests()
+            .with_label_values(&[&self.application, &bucket.to_string()])
+            .set(amount);
+    }
+
+    /// Puts the request at the back of the bucket of the shard, or
+    /// in place of the request with `ticket` if it is still waiting.
+    fn enqueue(
+        &self,
+        shard: u64,
+        total: u64,
+        ticket: Option<u64>,
+    ) -> Result<(u64, oneshot::Receiver<()>), QueueClosed> {
+        let (grant, granted) = oneshot::channel();
+        let state = self.state.read().expect("buckets lock poisoned");
+        if self.is_closed() {
+            return Err(QueueClosed);
+        }
+
+        let (id, bucket) = state.get(shard);
+        let mut reserved =
+            bucket.reserved.lock().expect("reserved lock poisoned");
+        let claimed = reserved.as_mut().filter(|v| {
+            ticket.is_some_and(|ticket| v.ticket == ticket && v.shard == shard)
+        });
+
+        // Its turn has already come, so it is granted right away
+        if let Some(waiter) = claimed {
+            waiter.grant = grant;
+            let ticket = waiter.ticket;
+            drop(reserved);
+
+            bucket.notify.notify_one();
+            return Ok((ticket, granted));
+        }
+        drop(reserved);
+
+        let mut waiters = bucket.waiters.lock().expect("waiters lock poisoned");
+        let resumed = ticket.and_then(|ticket| {
+            waiters.iter_mut().find(|v| v.ticket == ticket && v.shard == shard)
+        });
+
+        if let Some(waiter) = resumed {
+            waiter.grant = grant;
+            return Ok((waiter.ticket, granted));
+        }
+
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .waiting_requests()
+            .with_label_values(&[&self.application, &id.to_string()])
+            .inc();
+
+        waiters.push_back(Waiter {
+            ticket,
+            shard,
+            total,
+            grant,
+            queued_at: Instant::now(),
+            kept: false,
+        });
+        drop(waiters);
+
+        bucket.notify.notify_one();
+        Ok((ticket, granted))
+    }
+
+    /// Waits until the shard is allowed to identify.
+    ///
+    /// The request gives up its place in the bucket if the future
+    /// is dropped before its turn, like when the client disconnects.
+    pub async fn request(
+        &self,
+        shard: u64,
+        total: u64,
+    ) -> Result<(), QueueClosed> {
+        let (ticket, granted) = self.enqueue(shard, total, None)?;
+        let _pending = Pending { queue: self, shard, ticket, keep: false };
+        granted.await.map_err(|_| QueueClosed)
+    }
+
+    /// Waits until the shard is allowed to identify, for no longer
+    /// than `max_wait`.
+    ///
+    /// Unlike dropping the future, timing out keeps the place of the
+    /// request so it can ask again with the ticket from
+    /// [`RequestError::TimedOut`] without going to the back. If its
+    /// turn comes first, the turn is held for [`TICKET_GRACE`].
+    pub async fn request_within(
+        &self,
+        shard: u64,
+        total: u64,
+        ticket: Option<u64>,
+        max_wait: Duration,
+    ) -> Result<(), RequestError> {
+        let (ticket, mut granted) = self.enqueue(shard, total, ticket)?;
+        let mut pending = Pending { queue: self, shard, ticket, keep: false };
+        tokio::select! {
+            result = &mut granted => result.map_err(|_| QueueClosed.into()),
+            () = tokio::time::sleep(max_wait) => {
+                pending.keep = true;
+                self.keep_place(shard, ticket);
+
+                // It may have been granted before its place was kept
+                match granted.try_recv() {
+                    Ok(()) => Ok(()),
+                    Err(..) => Err(RequestError::TimedOut(ticket)),
+                }
+            },
+        }
+    }
+
+    /// Marks the waiting request so its turn is held for a while
+    /// if it comes before the request asks again.
+    fn keep_place(&self, shard: u64, ticket: u64) {
+        let state = self.state.read().expect("buckets lock poisoned");
+        let (_, bucket) = state.get(shard);
+        let mut waiters = bucket.waiters.lock().expect("waiters lock poisoned");
+        if let Some(waiter) = waiters.iter_mut().find(|v| v.ticket == ticket) {
+            waiter.kept = true;
+        }
+    }
+
+    /// Shards waiting in every bucket and when they are expected
+    /// to identify, assuming no request ahead of them is cancelled.
+    #[must_use]
+    pub fn status(&self) -> QueueStatus {
+        let state = self.state.read().expect("buckets lock poisoned");
+        let buckets = state
+            .buckets
+            .iter()
+            .zip(0..)
+            .map(|(bucket, id)| {
+                let ready_in = bucket.ready_in();
+                let waiters =
+                    bucket.waiters.lock().expect("waiters lock poisoned");
+
+                let waiting = waiters
+                    .iter()
+                    .enumerate()
+                    .map(|(ahead, waiter)| {
+                        let turns = u32::try_from(ahead).unwrap_or(u32::MAX);
+                        let eta = ready_in + IDENTIFY_INTERVAL * turns;
+                        WaiterStatus {
+                            shard: waiter.shard,
+                            total: waiter.total,
+                            position: ahead + 1,
+                            waited_secs: waiter.queued_at.elapsed().as_secs(),
+                            estimated_grant_secs: eta.as_secs(),
+                        }
+                    })
+                    .collect();
+
+                BucketStatus { bucket: id, waiting }
+            })
+            .collect();
+
+        let recent_grants = self
+            .recent_grants
+            .lock()
+            .expect("grants lock poisoned")
+            .iter()
+            .rev()
+            .map(|grant| GrantStatus {
+                shard: grant.shard,
+                total: grant.total,
+                bucket: grant.bucket,
+                waited_secs: grant.waited.as_secs(),
+                granted_secs_ago: grant.granted_at.elapsed().as_secs(),
+            })
+            .collect();
+
+        QueueStatus {
+            max_concurrency: state.buckets.len() as u64,
+            buckets,
+            recent_grants,
+        }
+    }
+}
+
+/// Removes the request from its bucket if it is gone before its
+/// turn, so it does not hold up the requests behind it.
+struct Pending<'a> {
+    queue: &'a BucketQueue,
+    shard: u64,
+    ticket: u64,
+    /// Whether the request keeps its place to be taken over later
+    keep: bool,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+
+        // The request may have moved to another bucket since
+        let state = self.queue.state.read().expect("buckets lock poisoned");
+        let (id, bucket) = state.get(self.shard);
+
+        let mut waiters = bucket.waiters.lock().expect("waiters lock poisoned");
+        let Some(index) = waiters.iter().position(|v| v.ticket == self.ticket)
+        else {
+            return;
+        };
+        waiters.remove(index);
+        drop(waiters);
+        drop(state);
+
+        let metrics = &self.queue.metrics;
+        let application = self.queue.application.as_str();
+        metrics
+            .waiting_requests()
+            .with_label_values(&[application, &id.to_string()])
+            .dec();
+
+        metrics.cancelled_requests().with_label_values(&[application]).inc();
+    }
+}
+
+impl Drop for BucketQueue {
+    fn drop(&mut self) {
+        let state = self.state.read().expect("buckets lock poisoned");
+        for task in &state.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn run_bucket(
+    application: String,
+    id: u64,
+    bucket: Arc<Bucket>,
+    metrics: Metrics,
+    recent_grants: RecentGrants,
+) {
+    let bucket_id = id.to_string();
+    let labels = [application.as_str(), bucket_id.as_str()];
+    let waiting = metrics.waiting_requests().with_label_values(&labels);
+    let latency = metrics.grant_latency().with_label_values(&labels);
+    let grants = metrics.grants().with_label_values(&labels);
+    let cancelled =
+        metrics.cancelled_requests().with_label_values(&labels[..1]);
+
+    // Buckets rebuilt for another concurrency continue from the
+    // last grant of the previous buckets
+    tokio::time::sleep(bucket.ready_in()).await;
+
+    loop {
+        let waiter =
+            bucket.waiters.lock().expect("waiters lock poisoned").pop_front();
+
+        let Some(waiter) = waiter else {
+            bucket.notify.notified().await;
+            continue;
+        };
+        waiting.dec();
+
+        let waiter = if waiter.kept && waiter.grant.is_closed() {
+            match reserve(&bucket, waiter).await {
+                Some(waiter) => waiter,
+                None => {
+                    cancelled.inc();
+                    continue;
+                },
+            }
+        } else {
+            waiter
+        };
+
+        // Requests gone before their turn do not use up the slot
+        if waiter.grant.send(()).is_ok() {
+            let granted_at = Instant::now();
+            *bucket.last_grant.lock().expect("grant lock poisoned") =
+                Some(granted_at);
+
+            let waited = granted_at - waiter.queued_at;
+            latency.observe(waited.as_secs_f64());
+            grants.inc();
+
+            let mut recent =
+                recent_grants.lock().expect("grants lock poisoned");
+            if recent.len() == RECENT_GRANTS {
+                recent.pop_front();
+            }
+            recent.push_back(Grant {
+                shard: waiter.shard,
+                total: waiter.total,
+                bucket: id,
+                waited,
+                granted_at,
+            });
+            drop(recent);
+
+            tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        } else {
+            cancelled.inc();
+        }
+    }
+}
+
+/// Holds the turn of a timed out request for [`TICKET_GRACE`],
+/// returning it once the request asks again with its ticket.
+async fn reserve(bucket: &Bucket, waiter: Waiter) -> Option<Waiter> {
+    *bucket.reserved.lock().expect("reserved lock poisoned") = Some(waiter);
+
+    let deadline = tokio::time::Instant::now() + TICKET_GRACE;
+    loop {
+        let notified = bucket.notify.notified();
+        {
+            let mut reserved =
+                bucket.reserved.lock().expect("reserved lock poisoned");
+            if reserved.as_ref().map_or(true, |v| !v.grant.is_closed()) {
+                return reserved.take();
+            }
+        }
+
+        if tokio::time::timeout_at(deadline, notified).await.is_err() {
+            let mut reserved =
+                bucket.reserved.lock().expect("reserved lock poisoned");
+            return reserved.take().filter(|v| !v.grant.is_closed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BucketQueue, QueueClosed, RequestError, IDENTIFY_INTERVAL, TICKET_GRACE,
+    };
+    use crate::metrics::Metrics;
+    use prometheus::Registry;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::task::JoinHandle;
+
+    const TOTAL: u64 = 4;
+
+    /// Queue with a single bucket whose grant is already taken,
+    /// so the next one is in `IDENTIFY_INTERVAL`.
+    async fn queue() -> (Arc<BucketQueue>, Metrics) {
+        tokio::time::pause();
+        let metrics = Metrics::register(&Registry::new()).unwrap();
+        let queue = BucketQueue::new("test".into(), 1, metrics.clone());
+        queue.request(0, TOTAL).await.unwrap();
+        (Arc::new(queue), metrics)
+    }
+
+    fn request(
+        queue: &Arc<BucketQueue>,
+        shard: u64,
+    ) -> JoinHandle<Result<(), QueueClosed>> {
+        let queue = queue.clone();
+        tokio::spawn(async move { queue.request(shard, TOTAL).await })
+    }
+
+    fn request_within(
+        queue: &Arc<BucketQueue>,
+        shard: u64,
+        ticket: Option<u64>,
+        max_wait: Duration,
+    ) -> JoinHandle<Result<(), RequestError>> {
+        let queue = queue.clone();
+        tokio::spawn(async move {
+            queue.request_within(shard, TOTAL, ticket, max_wait).await
+        })
+    }
+
+    /// Lets spawned tasks run without advancing the paused clock
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn waiting_shards(queue: &BucketQueue) -> Vec<u64> {
+        waiting_in(queue, 0)
+    }
+
+    fn waiting_in(queue: &BucketQueue, bucket: usize) -> Vec<u64> {
+        let status = queue.status();
+        status.buckets[bucket].waiting.iter().map(|v| v.shard).collect()
+    }
+
+    fn waiting(metrics: &Metrics) -> i64 {
+        waiting_on(metrics, 0)
+    }
+
+    fn waiting_on(metrics: &Metrics, bucket: u64) -> i64 {
+        let bucket = bucket.to_string();
+        metrics.waiting_requests().with_label_values(&["test", &bucket]).get()
+    }
+
+    fn cancelled(metrics: &Metrics) -> u64 {
+        metrics.cancelled_requests().with_label_values(&["test"]).get()
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_removed() {
+        let (queue, metrics) = queue().await;
+        let first = request(&queue, 1);
+        let second = request(&queue, 2);
+        let third = request(&queue, 3);
+        settle().await;
+        assert_eq!(waiting_shards(&queue), [1, 2, 3]);
+        assert_eq!(waiting(&metrics), 3);
+
+        second.abort();
+        settle().await;
+        assert_eq!(waiting_shards(&queue), [1, 3]);
+        assert_eq!(waiting(&metrics), 2);
+        assert_eq!(cancelled(&metrics), 1);
+
+        // The requests behind it move up a position
+        let status = queue.status();
+        let positions = status.buckets[0].waiting.iter().map(|v| v.position);
+        assert_eq!(positions.collect::<Vec<_>>(), [1, 2]);
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        first.await.unwrap().unwrap();
+        settle().await;
+        assert!(!third.is_finished());
+        assert_eq!(waiting(&metrics), 1);
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        third.await.unwrap().unwrap();
+        assert_eq!(waiting(&metrics), 0);
+        assert_eq!(cancelled(&metrics), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_request_keeps_its_place() {
+        let (queue, metrics) = queue().await;
+        let first = request_within(&queue, 1, None, Duration::from_secs(1));
+        let second = request(&queue, 2);
+
+        let Err(RequestError::TimedOut(ticket)) = first.await.unwrap() else {
+            panic!("request should time out");
+        };
+        assert_eq!(waiting_shards(&queue), [1, 2]);
+        assert_eq!(waiting(&metrics), 2);
+        assert_eq!(cancelled(&metrics), 0);
+
+        let resumed =
+            request_within(&queue, 1, Some(ticket), IDENTIFY_INTERVAL * 2);
+        settle().await;
+        assert_eq!(waiting_shards(&queue), [1, 2]);
+        assert_eq!(waiting(&metrics), 2);
+
+        resumed.await.unwrap().unwrap();
+        settle().await;
+        assert!(!second.is_finished());
+        assert_eq!(waiting(&metrics), 1);
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        second.await.unwrap().unwrap();
+        assert_eq!(waiting(&metrics), 0);
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_request_is_skipped() {
+        let (queue, metrics) = queue().await;
+        let first = request_within(&queue, 1, None, Duration::from_secs(1));
+        let second = request(&queue, 2);
+        assert!(matches!(
+            first.await.unwrap(),
+            Err(RequestError::TimedOut(..))
+        ));
+
+        // Its turn comes without anyone asking again for it, so the
+        // turn is only held until the grace period is over
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        settle().await;
+        assert!(!second.is_finished());
+
+        tokio::time::sleep(TICKET_GRACE).await;
+        second.await.unwrap().unwrap();
+        assert!(waiting_shards(&queue).is_empty());
+        assert_eq!(waiting(&metrics), 0);
+        assert_eq!(cancelled(&metrics), 1);
+    }
+
+    #[tokio::test]
+    async fn test_turn_is_held_until_request_asks_again() {
+        let (queue, metrics) = queue().await;
+        let first = request_within(&queue, 1, None, Duration::from_secs(1));
+        let second = request(&queue, 2);
+        let Err(RequestError::TimedOut(ticket)) = first.await.unwrap() else {
+            panic!("request should time out");
+        };
+
+        // The turn comes between the timeout and asking again
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        settle().await;
+        assert_eq!(waiting_shards(&queue), [2]);
+        assert!(!second.is_finished());
+
+        let resumed =
+            request_within(&queue, 1, Some(ticket), Duration::from_secs(1));
+        resumed.await.unwrap().unwrap();
+        settle().await;
+        assert!(!second.is_finished());
+        assert_eq!(cancelled(&metrics), 0);
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        second.await.unwrap().unwrap();
+        assert_eq!(waiting(&metrics), 0);
+        assert_eq!(cancelled(&metrics), 0);
+    }
+
+    #[tokio::test]
+    async fn test_buckets_grant_in_parallel() {
+        tokio::time::pause();
+        let metrics = Metrics::register(&Registry::new()).unwrap();
+        let queue =
+            Arc::new(BucketQueue::new("test".into(), 2, metrics.clone()));
+        let buckets = (0..6).map(|shard| queue.bucket(shard));
+        assert_eq!(buckets.collect::<Vec<_>>(), [0, 1, 0, 1, 0, 1]);
+
+        let requests = (0..6).map(|shard| request(&queue, shard));
+        let requests = requests.collect::<Vec<_>>();
+        settle().await;
+
+        // The first shard of every bucket identifies right away
+        assert!(requests[0].is_finished());
+        assert!(requests[1].is_finished());
+        assert_eq!(waiting_in(&queue, 0), [2, 4]);
+        assert_eq!(waiting_in(&queue, 1), [3, 5]);
+        assert_eq!(waiting_on(&metrics, 0), 2);
+        assert_eq!(waiting_on(&metrics, 1), 2);
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        settle().await;
+        assert!(requests[2].is_finished());
+        assert!(requests[3].is_finished());
+        assert!(!requests[4].is_finished());
+        assert!(!requests[5].is_finished());
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        for request in requests {
+            request.await.unwrap().unwrap();
+        }
+        assert_eq!(waiting_on(&metrics, 0), 0);
+        assert_eq!(waiting_on(&metrics, 1), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_keeps_waiting_requests() {
+        let (queue, metrics) = queue().await;
+        let requests = (1..4).map(|shard| request(&queue, shard));
+        let requests = requests.collect::<Vec<_>>();
+        settle().await;
+        assert_eq!(waiting_shards(&queue), [1, 2, 3]);
+
+        queue.set_max_concurrency(2);
+        assert_eq!(queue.max_concurrency(), 2);
+        assert_eq!(waiting_in(&queue, 0), [2]);
+        assert_eq!(waiting_in(&queue, 1), [1, 3]);
+        assert_eq!(waiting_on(&metrics, 0), 1);
+        assert_eq!(waiting_on(&metrics, 1), 2);
+
+        // The new buckets wait for the interval since the last grant
+        settle().await;
+        assert!(requests.iter().all(|request| !request.is_finished()));
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        settle().await;
+        assert!(requests[0].is_finished());
+        assert!(requests[1].is_finished());
+        assert!(!requests[2].is_finished());
+
+        tokio::time::sleep(IDENTIFY_INTERVAL).await;
+        for request in requests {
+            request.await.unwrap().unwrap();
+        }
+        assert_eq!(waiting_on(&metrics, 0), 0);
+        assert_eq!(waiting_on(&metrics, 1), 0);
+        assert_eq!(cancelled(&metrics), 0);
+    }
+
+    #[tokio::test]
+    async fn test_closed_queue_refuses_requests() {
+        let (queue, metrics) = queue().await;
+        let waiting_request = request(&queue, 1);
+        let timed_out = request_within(&queue, 2, None, Duration::from_secs(1));
+        let Err(RequestError::TimedOut(ticket)) = timed_out.await.unwrap()
+        else {
+            panic!("request should time out");
+        };
+        assert_eq!(waiting(&metrics), 2);
+
+        queue.close();
+        assert!(queue.is_closed());
+        assert!(matches!(waiting_request.await.unwrap(), Err(QueueClosed)));
+        assert!(waiting_shards(&queue).is_empty());
+        assert_eq!(waiting(&metrics), 0);
+
+        assert!(matches!(queue.request(3, TOTAL).await, Err(QueueClosed)));
+        let resumed = queue
+            .request_within(2, TOTAL, Some(ticket), Duration::from_secs(1))
+            .await;
+        assert!(matches!(resumed, Err(RequestError::Closed(..))));
+        assert!(waiting_shards(&queue).is_empty());
+    }
+}
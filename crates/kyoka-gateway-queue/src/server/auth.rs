@@ -0,0 +1,40 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+
+use super::AppContext;
+
+/// Checks the bearer token of the request against the shared
+/// secret, if the server is configured with one.
+///
+/// Rejected requests are counted in the `auth_failures` metric.
+pub fn authorize(
+    req: &HttpRequest,
+    ctx: &AppContext,
+) -> Result<(), HttpResponse> {
+    let Some(expected) = ctx.secret.as_ref() else {
+        return Ok(());
+    };
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    let reason = match token {
+        Some(token) if expected.eq_constant_time(token) => return Ok(()),
+        Some(..) => "invalid",
+        None => "missing",
+    };
+
+    tracing::warn!(
+        peer = ?req.peer_addr(),
+        reason,
+        "Rejected unauthenticated request"
+    );
+    ctx.metrics.auth_failures().with_label_values(&[reason]).inc();
+
+    Err(HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .finish())
+}
@@ -1,74 +1,115 @@
 use actix_web::{App, HttpServer};
+use actix_web_prom::PrometheusMetricsBuilder;
 use error_stack::{Result, ResultExt};
-use kyoka::perform_request;
+use kyoka::util::Sensitive;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use twilight_gateway_queue::{LargeBotQueue, LocalQueue, Queue};
+use thiserror::Error;
 
+use crate::application::Applications;
+use crate::coordinator::Coordinator;
+use crate::metrics::Metrics;
 use crate::{config, SetupError};
 
+mod auth;
 mod router;
 
+#[derive(Debug, Error)]
+#[error(
+    "Coordinator requires either a single bot token or `COORDINATOR_SHARD_TOTAL` to be set"
+)]
+struct MissingShardTotal;
+
 #[derive(Debug)]
 pub struct AppContext {
-    pub big_queue: bool,
-    pub queue: Arc<dyn Queue>,
+    pub applications: Applications,
+    pub coordinator: Option<Coordinator>,
+    pub metrics: Metrics,
+    pub secret: Option<Sensitive<String>>,
+    pub session_floor: u64,
 }
 
 pub async fn run(cfg: config::Server) -> Result<(), SetupError> {
     tracing::info!(host = %cfg.host(), port = %cfg.port(), "Starting gateway queue server...");
 
-    let (queue, big_queue): (Arc<dyn Queue>, bool) = if let Some(token) =
-        cfg.token()
-    {
-        tracing::info!(
-            "`DISCORD_BOT_TOKEN` is present, getting maximum concurrent sessions"
-        );
-        let mut http =
-            twilight_http::Client::builder().token(token.to_string());
+    let prometheus = PrometheusMetricsBuilder::new("gateway_queue")
+        .endpoint("/metrics")
+        .build()
+        .expect("failed to initialize metrics");
 
-        if let Some(proxy_url) = cfg.proxy_url() {
-            http = http.proxy(proxy_url.into(), cfg.proxy_use_http());
-        }
+    let metrics =
+        Metrics::register(&prometheus.registry).change_context(SetupError)?;
 
-        let http = Arc::new(http.build());
-        let gateway =
-            perform_request!(http.gateway().authed(), SetupError).await?;
+    let applications = Applications::from_config(&cfg, &metrics).await?;
 
-        let concurrency =
-            gateway.session_start_limit.max_concurrency.try_into().unwrap();
+    let coordinator = if let Some(coordinator) = cfg.coordinator() {
+        let total = coordinator
+            .shard_total()
+            .map(|v| v.get())
+            .or(applications.recommended_shards())
+            .ok_or(MissingShardTotal)
+            .change_context(SetupError)?;
 
-        let queue = Arc::new(LargeBotQueue::new(concurrency, http).await);
+        let per_process = coordinator.shards_per_process().get();
+        tracing::info!(
+            "Coordinating {total} shard/s with {per_process} shard/s per process"
+        );
 
-        tracing::info!("Received {concurrency} maximum concurrent sessions");
-        (queue, concurrency > 1)
+        Some(Coordinator::new(
+            total,
+            per_process,
+            coordinator.heartbeat_timeout(),
+        ))
     } else {
-        tracing::info!("`DISCORD_BOT_TOKEN` is not present, using local queue");
-        (Arc::new(LocalQueue::new()), false)
+        None
     };
 
     let address = SocketAddr::from((cfg.host(), cfg.port()));
     tracing::info!("Listening at http://{address}");
 
-    // let prometheus = PrometheusMetricsBuilder::new("gateway_queue")
-    //     .endpoint("/metrics")
-    //     .build()
-    //     .expect("failed to initialize metrics");
-
-    let context = actix_web::web::Data::new(AppContext { big_queue, queue });
-    HttpServer::new(move || {
-        App::new()
-            .wrap(sentry_actix::Sentry::new())
-            // .wrap(prometheus.clone())
-            .app_data(context.clone())
-            .configure(router::configure)
+    let secret = cfg.secret().cloned();
+    if secret.is_none() {
+        tracing::warn!(
+            "`GATEWAY_QUEUE_SECRET` is not set, anyone can use the queue"
+        );
+    }
+
+    let context = actix_web::web::Data::new(AppContext {
+        applications,
+        coordinator,
+        metrics,
+        secret,
+        session_floor: cfg.session_limit().floor(),
+    });
+    let server = HttpServer::new({
+        let context = context.clone();
+        move || {
+            App::new()
+                .wrap(sentry_actix::Sentry::new())
+                .wrap(prometheus.clone())
+                .app_data(context.clone())
+                .configure(router::configure)
+        }
     })
     .workers(1)
+    .disable_signals()
     .bind((cfg.host(), cfg.port()))
     .change_context(SetupError)?
-    .run()
-    .await
-    .change_context(SetupError)?;
+    .run();
+
+    // Waiting clients are told to retry, so they get their turn
+    // from another instance instead of waiting until they time out.
+    let handle = server.handle();
+    tokio::spawn(async move {
+        kyoka::util::shutdown_signal().await;
+        tracing::info!("Received shutdown signal, draining waiting requests");
+
+        for application in context.applications.iter() {
+            application.queue().close();
+        }
+        handle.stop(true).await;
+    });
+
+    server.await.change_context(SetupError)?;
 
     tracing::info!("Stopping gateway queue server...");
     Ok(())
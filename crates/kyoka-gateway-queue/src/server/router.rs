@@ -1,38 +1,369 @@
+use actix_web::http::header;
 use actix_web::web::{self, ServiceConfig};
-use actix_web::HttpResponse;
-use serde::Deserialize;
+use actix_web::{HttpRequest, HttpResponse};
+use kyoka::util::{
+    Handshake, SessionLimitReport, GATEWAY_QUEUE_PROTOCOL,
+    GATEWAY_QUEUE_TICKET_HEADER,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
+use super::auth::authorize;
 use super::AppContext;
+use crate::application::{Application, LookupError};
+use crate::coordinator::{Heartbeat, HeartbeatRequest};
+use crate::metrics::Metrics;
+use crate::queue::{QueueStatus, RequestError};
+
+/// How long clients should wait before asking again once the
+/// server is shutting down, expecting another instance by then
+const DRAINING_RETRY_AFTER_SECS: u64 = 5;
+
+fn draining() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, DRAINING_RETRY_AFTER_SECS))
+        .body("Gateway queue is shutting down")
+}
 
 #[tracing::instrument]
-pub async fn health() -> HttpResponse {
+pub async fn live() -> HttpResponse {
     HttpResponse::Ok().body("I'm healthy and alive!")
 }
 
+#[derive(Debug, Serialize)]
+struct ReadyReport {
+    ready: bool,
+    big_queue: bool,
+}
+
+/// The queue is ready as soon as the server starts accepting
+/// requests, since it's initialized before the server starts.
+///
+/// It stops being ready once the queues are closed for shutdown.
+#[tracing::instrument(skip_all)]
+pub async fn ready(ctx: web::Data<AppContext>) -> HttpResponse {
+    let big_queue = ctx
+        .applications
+        .iter()
+        .any(|application| application.queue().max_concurrency() > 1);
+
+    let closed = ctx
+        .applications
+        .iter()
+        .any(|application| application.queue().is_closed());
+
+    if closed {
+        HttpResponse::ServiceUnavailable()
+            .json(ReadyReport { ready: false, big_queue })
+    } else {
+        HttpResponse::Ok().json(ReadyReport { ready: true, big_queue })
+    }
+}
+
+fn find_application(
+    ctx: &AppContext,
+    id: Option<u64>,
+) -> Result<&Application, HttpResponse> {
+    ctx.applications.get(id).map_err(|error| match error {
+        LookupError::Missing => {
+            HttpResponse::BadRequest().body(error.to_string())
+        },
+        LookupError::Unknown => {
+            HttpResponse::NotFound().body(error.to_string())
+        },
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct HandshakeParams {
+    pub application: Option<u64>,
+    pub protocol: u32,
+}
+
+/// Checks whether the client can use this queue, without taking
+/// any identify slot or session start.
+#[tracing::instrument(
+    skip_all,
+    fields(params.application = ?query.application)
+)]
+pub async fn handshake(
+    req: HttpRequest,
+    query: web::Query<HandshakeParams>,
+    ctx: web::Data<AppContext>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    if query.protocol != GATEWAY_QUEUE_PROTOCOL {
+        return HttpResponse::BadRequest().body(format!(
+            "Protocol version {} is not supported, expected {}",
+            query.protocol, GATEWAY_QUEUE_PROTOCOL
+        ));
+    }
+
+    match find_application(&ctx, query.application) {
+        Ok(application) if application.queue().is_closed() => draining(),
+        Ok(application) => HttpResponse::Ok().json(Handshake {
+            protocol: GATEWAY_QUEUE_PROTOCOL,
+            application: application.id(),
+            max_concurrency: application.queue().max_concurrency(),
+        }),
+        Err(response) => response,
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct QueryParams {
-    pub shard: Option<u64>,
+    pub application: Option<u64>,
+    pub shard: u64,
+    pub total: u64,
+    /// Maximum time to wait for the turn to identify in seconds
+    pub max_wait: Option<u64>,
+    /// Ticket of a previous request which timed out, to take over
+    /// its place in the queue
+    pub ticket: Option<u64>,
+}
+
+/// Gives back the session start taken by a request if it
+/// is gone before its turn to identify.
+struct SessionStart<'a> {
+    application: &'a Application,
+    metrics: &'a Metrics,
+    used: bool,
+}
+
+impl Drop for SessionStart<'_> {
+    fn drop(&mut self) {
+        if !self.used {
+            self.application.limiter().release();
+            self.application.record_session_starts(self.metrics);
+        }
+    }
 }
 
-#[tracing::instrument(skip_all, fields(params.id = ?query.shard))]
+#[tracing::instrument(
+    skip_all,
+    fields(
+        params.application = ?query.application,
+        params.id = %query.shard,
+        params.total = %query.total
+    )
+)]
 pub async fn index(
+    req: HttpRequest,
     query: web::Query<QueryParams>,
     ctx: web::Data<AppContext>,
 ) -> HttpResponse {
-    let shard = query.shard;
-    if shard.is_none() && ctx.big_queue {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    if query.shard >= query.total {
+        return HttpResponse::BadRequest()
+            .body("Shard id must be less than the total amount of shards");
+    }
+
+    let application = match find_application(&ctx, query.application) {
+        Ok(application) => application,
+        Err(response) => return response,
+    };
+
+    if application.queue().is_closed() {
+        return draining();
+    }
+
+    let limiter = application.limiter();
+    if let Err(wait) = limiter.acquire(ctx.session_floor) {
         tracing::warn!(
-            "No shard id set, defaulting to 0. Will not bucket requests correctly!"
+            ?wait,
+            floor = %ctx.session_floor,
+            "Session start limit is almost exhausted, refusing to identify"
         );
+        return HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, wait.as_secs().max(1)))
+            .body("Session start limit is almost exhausted");
     }
 
-    let shard = shard.unwrap_or(0);
-    ctx.queue.request([shard, 1]).await;
+    application.record_session_starts(&ctx.metrics);
+
+    let mut session_start =
+        SessionStart { application, metrics: &ctx.metrics, used: false };
+    let queue = application.queue();
+    let result = if let Some(max_wait) = query.max_wait {
+        let max_wait = Duration::from_secs(max_wait);
+        queue
+            .request_within(query.shard, query.total, query.ticket, max_wait)
+            .await
+    } else {
+        queue.request(query.shard, query.total).await.map_err(Into::into)
+    };
+
+    match result {
+        Ok(()) => session_start.used = true,
+        Err(RequestError::Closed(..)) => return draining(),
+        Err(RequestError::TimedOut(ticket)) => {
+            tracing::warn!(%ticket, "Request timed out waiting for its turn");
+            return HttpResponse::RequestTimeout()
+                .insert_header((GATEWAY_QUEUE_TICKET_HEADER, ticket))
+                .body("Gave up waiting for the turn to identify");
+        },
+    }
 
     HttpResponse::Ok().body("You're good to initialize session. :)")
 }
 
+#[derive(Debug, Serialize)]
+struct ApplicationLimit {
+    application: Option<u64>,
+    limit: Option<SessionLimitReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionLimitResponse {
+    floor: u64,
+    applications: Vec<ApplicationLimit>,
+}
+
+/// Session start limit of every application as tracked by this
+/// server, which is unknown if no bot tokens are configured.
+#[tracing::instrument(skip_all)]
+async fn session_limit(
+    req: HttpRequest,
+    ctx: web::Data<AppContext>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    let applications = ctx
+        .applications
+        .iter()
+        .map(|application| ApplicationLimit {
+            application: application.id(),
+            limit: application.limiter().report(),
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .json(SessionLimitResponse { floor: ctx.session_floor, applications })
+}
+
+#[derive(Debug, Serialize)]
+struct ApplicationStatus {
+    application: Option<u64>,
+    #[serde(flatten)]
+    queue: QueueStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusResponse {
+    applications: Vec<ApplicationStatus>,
+}
+
+/// Shards waiting to identify with their estimated grant time and
+/// the most recent grants of every application.
+#[tracing::instrument(skip_all)]
+async fn status(req: HttpRequest, ctx: web::Data<AppContext>) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    let applications = ctx
+        .applications
+        .iter()
+        .map(|application| ApplicationStatus {
+            application: application.id(),
+            queue: application.queue().status(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(StatusResponse { applications })
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterRequest {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnregisterRequest {
+    process: String,
+}
+
+fn coordinator_disabled() -> HttpResponse {
+    HttpResponse::NotFound().body("Coordinator is not enabled")
+}
+
+#[tracing::instrument(skip(req, ctx))]
+async fn register(
+    req: HttpRequest,
+    body: web::Json<RegisterRequest>,
+    ctx: web::Data<AppContext>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    let Some(coordinator) = &ctx.coordinator else {
+        return coordinator_disabled();
+    };
+
+    match coordinator.register(body.into_inner().name) {
+        Some(assignment) => HttpResponse::Ok().json(assignment),
+        None => HttpResponse::ServiceUnavailable()
+            .body("Every shard range is taken by other processes"),
+    }
+}
+
+#[tracing::instrument(skip(req, ctx))]
+async fn heartbeat(
+    req: HttpRequest,
+    body: web::Json<HeartbeatRequest>,
+    ctx: web::Data<AppContext>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    let Some(coordinator) = &ctx.coordinator else {
+        return coordinator_disabled();
+    };
+
+    match coordinator.heartbeat(body.into_inner()) {
+        Heartbeat::Accepted => HttpResponse::NoContent().finish(),
+        Heartbeat::Reassigned => HttpResponse::Conflict()
+            .body("Shard range is assigned to another process"),
+    }
+}
+
+#[tracing::instrument(skip(req, ctx))]
+async fn unregister(
+    req: HttpRequest,
+    body: web::Json<UnregisterRequest>,
+    ctx: web::Data<AppContext>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    let Some(coordinator) = &ctx.coordinator else {
+        return coordinator_disabled();
+    };
+
+    coordinator.unregister(&body.process);
+    HttpResponse::NoContent().finish()
+}
+
 pub fn configure(cfg: &mut ServiceConfig) {
-    cfg.route("/health", web::get().to(health))
-        .route("/queue", web::post().to(index));
+    cfg.route("/health", web::get().to(live))
+        .route("/health/live", web::get().to(live))
+        .route("/health/ready", web::get().to(ready))
+        .route("/queue", web::post().to(index))
+        .route("/queue/handshake", web::get().to(handshake))
+        .route("/queue/limit", web::get().to(session_limit))
+        .route("/queue/status", web::get().to(status))
+        .route("/coordinator/register", web::post().to(register))
+        .route("/coordinator/heartbeat", web::post().to(heartbeat))
+        .route("/coordinator/unregister", web::post().to(unregister));
 }
@@ -1,12 +1,16 @@
 use error_stack::{Result, ResultExt};
+use kyoka::util::SessionLimiter;
 use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
 
-use crate::{metrics::Metrics, SetupError};
+use crate::{metrics::Metrics, presence::Presence, shards::Shards, SetupError};
 
 /// Holds all state for metrics server and Discord bot client
 #[derive(Debug, Clone)]
 pub struct App {
     metrics: Metrics,
+    presence: Presence,
+    session_limiter: SessionLimiter,
+    shards: Shards,
     shutdown_signal: CancellationToken,
 }
 
@@ -16,16 +20,50 @@ impl App {
         Ok(Self {
             metrics: Metrics::register(prometheus::default_registry())
                 .change_context(SetupError)?,
+            presence: Presence::default(),
+            session_limiter: SessionLimiter::default(),
+            shards: Shards::default(),
             shutdown_signal: CancellationToken::new(),
         })
     }
 }
 
+#[cfg(test)]
+impl App {
+    /// Creates an app with metrics registered to their own registry,
+    /// so every test can have its own.
+    pub(crate) fn for_test() -> Self {
+        Self {
+            metrics: Metrics::register(&prometheus::Registry::new())
+                .expect("metrics should be registered"),
+            presence: Presence::default(),
+            session_limiter: SessionLimiter::default(),
+            shards: Shards::default(),
+            shutdown_signal: CancellationToken::new(),
+        }
+    }
+}
+
 impl App {
     pub fn metrics(&self) -> &Metrics {
         &self.metrics
     }
 
+    /// Presence of the bot, which can be changed at runtime
+    pub fn presence(&self) -> &Presence {
+        &self.presence
+    }
+
+    /// Session start limit of the bot, shared by all shards
+    pub fn session_limiter(&self) -> &SessionLimiter {
+        &self.session_limiter
+    }
+
+    /// Status of all shards running in this process
+    pub fn shards(&self) -> &Shards {
+        &self.shards
+    }
+
     pub fn has_shutdown(&self) -> bool {
         self.shutdown_signal.is_cancelled()
     }
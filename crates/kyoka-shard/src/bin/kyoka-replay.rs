@@ -0,0 +1,33 @@
+use error_stack::{Result, ResultExt};
+use kyoka_shard::bot::replay::{self, ReplayError};
+use std::path::PathBuf;
+
+/// Replays gateway events recorded by kyoka-shard (with
+/// `GATEWAY_RECORD_DIR` set) against a mock HTTP backend.
+///
+/// It reads the same environment variables as kyoka-shard, but
+/// it never connects to Discord. `DISCORD_BOT_TOKEN` still has
+/// to be set, although it can be any value.
+fn main() -> Result<(), ReplayError> {
+    kyoka::util::init_logging().change_context(ReplayError)?;
+
+    let Some(path) = std::env::args_os().nth(1).map(PathBuf::from) else {
+        eprintln!("Usage: kyoka-replay <recording file or directory>");
+        std::process::exit(2);
+    };
+
+    let summary = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .change_context(ReplayError)?
+        .block_on(replay::run(&path))?;
+
+    tracing::info!(
+        events = %summary.events,
+        failed = %summary.failed,
+        requests = %summary.requests,
+        "Replay finished"
+    );
+
+    Ok(())
+}
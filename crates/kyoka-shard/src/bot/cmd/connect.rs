@@ -1,12 +1,73 @@
 use super::prelude::*;
+use twilight_model::guild::Permissions;
+use twilight_model::id::marker::UserMarker;
+
+const REQUIRED_PERMISSIONS: Permissions =
+    Permissions::CONNECT.union(Permissions::SPEAK);
 
 #[async_trait]
 impl Runner for cmd::Join {
+    #[tracing::instrument]
     async fn run(
         &self,
         state: &State,
         interaction: &Interaction,
     ) -> Result<(), RunError> {
-        todo!()
+        // Joining a voice channel may take longer than the deadline
+        // to respond to the interaction
+        defer_ephemeral(state, interaction).await?;
+
+        let (Some(guild_id), Some(user_id)) =
+            (interaction.guild_id, interaction.author_id())
+        else {
+            return update_reply(
+                state,
+                interaction,
+                "This command can only be used inside a server.",
+            )
+            .await;
+        };
+
+        // Voice states and permissions are taken from the cache,
+        // saving us a few REST requests from Discord.
+        let channel_id = state
+            .cache()
+            .voice_state(user_id, guild_id)
+            .map(|voice_state| voice_state.channel_id());
+
+        let Some(channel_id) = channel_id else {
+            return update_reply(
+                state,
+                interaction,
+                "You must join a voice channel first before using this command.",
+            )
+            .await;
+        };
+
+        let bot_id = state.info().id.cast::<UserMarker>();
+        let permissions = state
+            .cache()
+            .permissions()
+            .in_channel(bot_id, channel_id)
+            .change_context(RunError)
+            .attach_printable("could not calculate permissions from cache")?;
+
+        if !permissions.contains(REQUIRED_PERMISSIONS) {
+            return update_reply(
+                state,
+                interaction,
+                "I don't have permission to connect or speak in your voice channel.",
+            )
+            .await;
+        }
+
+        state
+            .voice()
+            .join(guild_id, channel_id)
+            .await
+            .change_context(RunError)?;
+
+        let content = format!("Joined <#{channel_id}>!");
+        update_reply(state, interaction, &content).await
     }
 }
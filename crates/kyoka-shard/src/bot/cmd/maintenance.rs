@@ -0,0 +1,66 @@
+use super::prelude::*;
+
+/// Maintenance mode is kept by every process on its own, so
+/// owners have to toggle it on every process of the bot.
+const PROCESS_NOTE: &str = "This only applies to the shards in this \
+    process; other processes of the bot keep their own maintenance mode.";
+
+/// Toggles maintenance mode with `set` if the user owns the bot,
+/// returning the reply to the user.
+fn toggle(is_owner: bool, enabled: bool, set: impl FnOnce(bool)) -> String {
+    if !is_owner {
+        return "Only the owners of this bot can toggle maintenance mode."
+            .to_string();
+    }
+
+    set(enabled);
+    let status = if enabled { "enabled" } else { "disabled" };
+    format!("Maintenance mode is now {status}. {PROCESS_NOTE}")
+}
+
+#[async_trait]
+impl Runner for cmd::Maintenance {
+    #[tracing::instrument]
+    async fn run(
+        &self,
+        state: &State,
+        interaction: &Interaction,
+    ) -> Result<(), RunError> {
+        let is_owner =
+            interaction.author_id().is_some_and(|id| state.is_owner(id));
+
+        let content = toggle(is_owner, self.enabled, |enabled| {
+            state.set_maintenance(enabled);
+        });
+        reply_ephemeral(state, interaction, &content).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::toggle;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_toggle() {
+        let maintenance = Cell::new(false);
+        let set = |enabled| maintenance.set(enabled);
+
+        let reply = toggle(true, true, set);
+        assert!(maintenance.get());
+        assert!(reply.starts_with("Maintenance mode is now enabled."));
+        assert!(reply.contains("only applies to the shards in this process"));
+
+        let reply = toggle(true, false, set);
+        assert!(!maintenance.get());
+        assert!(reply.starts_with("Maintenance mode is now disabled."));
+    }
+
+    #[test]
+    fn test_toggle_by_others() {
+        let maintenance = Cell::new(false);
+        let reply = toggle(false, true, |enabled| maintenance.set(enabled));
+        assert!(!maintenance.get());
+        assert!(reply.starts_with("Only the owners"));
+    }
+}
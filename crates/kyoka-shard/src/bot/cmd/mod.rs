@@ -1,11 +1,17 @@
 mod connect;
+mod maintenance;
 mod ping;
 
 use async_trait::async_trait;
-use error_stack::Result;
+use error_stack::{Result, ResultExt};
 use thiserror::Error;
 use twilight_interactions::command::{CommandModel, CreateCommand};
 use twilight_model::application::interaction::Interaction;
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::http::interaction::{
+    InteractionResponse, InteractionResponseType,
+};
+use twilight_util::builder::InteractionResponseDataBuilder;
 
 use crate::bot::State;
 
@@ -22,11 +28,84 @@ pub trait Runner: CreateCommand + CommandModel {
     ) -> Result<(), RunError>;
 }
 
+async fn respond_ephemeral(
+    state: &State,
+    interaction: &Interaction,
+    kind: InteractionResponseType,
+    content: Option<&str>,
+) -> Result<(), RunError> {
+    let mut data =
+        InteractionResponseDataBuilder::new().flags(MessageFlags::EPHEMERAL);
+
+    if let Some(content) = content {
+        data = data.content(content);
+    }
+
+    let response = InteractionResponse { kind, data: Some(data.build()) };
+    state
+        .interaction()
+        .create_response(interaction.id, &interaction.token, &response)
+        .await
+        .change_context(RunError)?;
+
+    Ok(())
+}
+
+/// Replies to the interaction with a message only the user can see
+pub async fn reply_ephemeral(
+    state: &State,
+    interaction: &Interaction,
+    content: &str,
+) -> Result<(), RunError> {
+    respond_ephemeral(
+        state,
+        interaction,
+        InteractionResponseType::ChannelMessageWithSource,
+        Some(content),
+    )
+    .await
+}
+
+/// Acknowledges the interaction with a loading message only the
+/// user can see, for commands which may take longer than Discord's
+/// 3 seconds deadline. It must be followed by [`update_reply`].
+pub async fn defer_ephemeral(
+    state: &State,
+    interaction: &Interaction,
+) -> Result<(), RunError> {
+    respond_ephemeral(
+        state,
+        interaction,
+        InteractionResponseType::DeferredChannelMessageWithSource,
+        None,
+    )
+    .await
+}
+
+/// Replaces the content of the original response to the interaction
+pub async fn update_reply(
+    state: &State,
+    interaction: &Interaction,
+    content: &str,
+) -> Result<(), RunError> {
+    state
+        .interaction()
+        .update_response(&interaction.token)
+        .content(Some(content))
+        .change_context(RunError)?
+        .await
+        .change_context(RunError)?;
+
+    Ok(())
+}
+
 mod prelude {
     #[allow(unused)]
     pub(crate) use kyoka::perform_request;
 
-    pub use super::{RunError, Runner};
+    pub use super::{
+        defer_ephemeral, reply_ephemeral, update_reply, RunError, Runner,
+    };
     pub use crate::bot::State;
 
     pub use async_trait::async_trait;
@@ -0,0 +1,90 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+use twilight_gateway::MessageSender;
+
+/// Set of shards created with the same total amount of shards.
+///
+/// There's only one active generation at a time. The new generation
+/// connects while the current one is still active when resharding,
+/// but its events are not processed until it becomes active.
+#[derive(Debug)]
+pub struct Generation {
+    active: AtomicBool,
+    ready: Mutex<HashSet<u64>>,
+    retire: CancellationToken,
+    /// Message senders of the latest shard running for each id
+    senders: Mutex<HashMap<u64, MessageSender>>,
+    total: u64,
+}
+
+impl Generation {
+    #[must_use]
+    pub fn new(total: u64, active: bool) -> Arc<Self> {
+        Arc::new(Self {
+            active: AtomicBool::new(active),
+            ready: Mutex::new(HashSet::new()),
+            retire: CancellationToken::new(),
+            senders: Mutex::new(HashMap::new()),
+            total,
+        })
+    }
+
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Whether events received by shards in this generation
+    /// are processed and their status reported
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn set_active(&self, active: bool) {
+        self.active.store(active, Ordering::Relaxed);
+    }
+
+    /// Sets the message sender of the shard. It has to be set again
+    /// if the shard has been restarted.
+    pub fn set_sender(&self, shard: u64, sender: MessageSender) {
+        let mut senders = self.senders.lock().expect("senders lock poisoned");
+        senders.insert(shard, sender);
+    }
+
+    /// Message senders of every shard in this generation
+    #[must_use]
+    pub fn senders(&self) -> Vec<(u64, MessageSender)> {
+        let senders = self.senders.lock().expect("senders lock poisoned");
+        senders.iter().map(|(id, sender)| (*id, sender.clone())).collect()
+    }
+
+    /// Marks the shard that it received `Ready` event
+    pub fn mark_ready(&self, shard: u64) {
+        self.ready.lock().expect("ready lock poisoned").insert(shard);
+    }
+
+    /// Whether every shard in this generation has received `Ready`
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        let ready = self.ready.lock().expect("ready lock poisoned");
+        ready.len() as u64 >= self.total
+    }
+
+    /// Disconnects all shards in this generation for good
+    pub fn retire(&self) {
+        self.set_active(false);
+        self.retire.cancel();
+    }
+
+    #[must_use]
+    pub fn is_retired(&self) -> bool {
+        self.retire.is_cancelled()
+    }
+
+    pub fn retired(&self) -> WaitForCancellationFuture<'_> {
+        self.retire.cancelled()
+    }
+}
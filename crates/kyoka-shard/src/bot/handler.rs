@@ -1,9 +1,13 @@
 use error_stack::{Result, ResultExt};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendTimeoutError, TrySendError};
 use tokio_util::task::TaskTracker;
 use tracing::Instrument;
-use twilight_gateway::error::ReceiveMessageErrorType;
-use twilight_gateway::{CloseFrame, Event, Message, Shard};
+use twilight_gateway::error::{ReceiveMessageError, ReceiveMessageErrorType};
+use twilight_gateway::{CloseFrame, Event, Message, MessageSender, Shard};
 use twilight_interactions::command::CommandModel;
 use twilight_model::application::interaction::InteractionData;
 use twilight_model::application::interaction::{
@@ -14,8 +18,13 @@ use twilight_model::http::interaction::{
 };
 use twilight_util::builder::InteractionResponseDataBuilder;
 
-use super::cmd::{RunError, Runner};
+use super::cmd::{self, RunError, Runner};
+use super::generation::Generation;
+use super::recorder::Recorder;
+use super::session::ShardSession;
 use super::State;
+use crate::config::OverflowPolicy;
+use crate::shards::ShardStatus;
 
 #[derive(Debug, Error)]
 #[error("Failed to process event")]
@@ -33,6 +42,16 @@ async fn command(
             .run(state, interaction)
             .await
             .change_context(RunError),
+        "join" => kyoka::cmd::Join::from_interaction(data.into())
+            .change_context(RunError)?
+            .run(state, interaction)
+            .await
+            .change_context(RunError),
+        "maintenance" => kyoka::cmd::Maintenance::from_interaction(data.into())
+            .change_context(RunError)?
+            .run(state, interaction)
+            .await
+            .change_context(RunError),
         _ => {
             tracing::warn!("Unknown command: {:?}", data.name);
             Err(RunError.into())
@@ -40,8 +59,23 @@ async fn command(
     }
 }
 
+/// Replies to the user that the bot is in maintenance mode
+/// instead of running the command they invoked.
+async fn maintenance_notice(
+    state: &State,
+    interaction: &Interaction,
+) -> Result<(), EventFailed> {
+    let content = state.config().maintenance().message();
+    cmd::reply_ephemeral(state, interaction, content)
+        .await
+        .change_context(EventFailed)
+}
+
 #[tracing::instrument(skip_all, name = "event", fields(kind = ?event.kind()))]
-async fn process_event(state: State, event: Event) -> Result<(), EventFailed> {
+pub(super) async fn process_event(
+    state: State,
+    event: Event,
+) -> Result<(), EventFailed> {
     match event {
         Event::GatewayHello(..) => {
             tracing::debug!("Received Hello event, identifying bot...");
@@ -60,14 +94,27 @@ async fn process_event(state: State, event: Event) -> Result<(), EventFailed> {
                 _ => return Ok(()),
             };
 
+            // Owners are still allowed to run commands, especially
+            // for turning off maintenance mode.
+            let is_owner =
+                interaction.author_id().is_some_and(|id| state.is_owner(id));
+
+            if state.in_maintenance() && !is_owner {
+                tracing::debug!(
+                    "Bot is in maintenance mode; rejecting command"
+                );
+                return maintenance_notice(&state, &interaction).await;
+            }
+
             if let Err(error) = command(&state, &interaction, data).await {
                 tracing::error!(
                     ?error,
                     "Failed to process command interaction"
                 );
 
+                let content = "There's something wrong with your request. Please report this to the developers immediately!";
                 let data = InteractionResponseDataBuilder::new()
-                    .content("There's something wrong with your request. Please report this to the developers immediately!")
+                    .content(content)
                     .build();
 
                 let response = InteractionResponse {
@@ -75,15 +122,22 @@ async fn process_event(state: State, event: Event) -> Result<(), EventFailed> {
                     data: Some(data),
                 };
 
-                state
+                let result = state
                     .interaction()
                     .create_response(
                         interaction.id,
                         &interaction.token,
                         &response,
                     )
-                    .await
-                    .change_context(EventFailed)?;
+                    .await;
+
+                // Commands which deferred their response already
+                // responded to the interaction
+                if result.is_err() {
+                    cmd::update_reply(&state, &interaction, content)
+                        .await
+                        .change_context(EventFailed)?;
+                }
             }
         },
         _ => {},
@@ -91,57 +145,350 @@ async fn process_event(state: State, event: Event) -> Result<(), EventFailed> {
     Ok(())
 }
 
+/// Keeps track of per-shard metrics while the shard is running
+/// and reports its status to [`Shards`](crate::shards::Shards).
+///
+/// Shards only report their status while their generation
+/// is active, since shards with the same id may be running
+/// in another generation while resharding.
+struct ShardMetrics {
+    id: u64,
+    label: String,
+    connections: u64,
+    generation: Arc<Generation>,
+    /// Whether `Ready` or `Resumed` is received in the current session
+    ready: bool,
+    sender: MessageSender,
+    status: Option<ShardStatus>,
+}
+
+impl ShardMetrics {
+    fn new(shard: &Shard, generation: Arc<Generation>) -> Self {
+        let id = shard.id().number();
+        Self {
+            id,
+            label: id.to_string(),
+            connections: 0,
+            generation,
+            ready: false,
+            sender: shard.sender(),
+            status: None,
+        }
+    }
+
+    fn update_status(&mut self, state: &State, status: ShardStatus) {
+        if status != ShardStatus::Active {
+            self.ready = false;
+        }
+
+        if !self.generation.is_active() {
+            return;
+        }
+
+        let shards = state.app().shards();
+        let first_report = self.status.is_none();
+        if first_report {
+            shards.set_sender(self.id, self.sender.clone());
+        }
+
+        if self.status != Some(status) {
+            self.status = Some(status);
+            state.app().metrics().set_shard_status(&self.label, status);
+            shards.set_status(self.id, status);
+        }
+
+        // Shards of a new generation are ready before it becomes active
+        if first_report && self.ready {
+            shards.set_ready(self.id);
+        }
+    }
+
+    fn record(&mut self, state: &State, shard: &Shard, event: &Event) {
+        match event {
+            // Every new connection starts with a Hello event
+            Event::GatewayHello(..) => self.connections += 1,
+            Event::Ready(..) => {
+                self.generation.mark_ready(self.id);
+                self.ready = true;
+            },
+            Event::Resumed => self.ready = true,
+            _ => {},
+        }
+
+        self.update_status(state, shard.status().into());
+        if !self.generation.is_active() {
+            return;
+        }
+
+        let metrics = state.app().metrics();
+        let kind = format!("{:?}", event.kind());
+        metrics
+            .events_processed()
+            .with_label_values(&[&self.label, &kind])
+            .inc();
+
+        match event {
+            Event::GatewayHello(..) if self.connections > 1 => {
+                metrics
+                    .shard_reconnects()
+                    .with_label_values(&[&self.label])
+                    .inc();
+            },
+            Event::GatewayHeartbeatAck => {
+                if let Some(latency) = shard.latency().recent().first() {
+                    metrics
+                        .shard_latency()
+                        .with_label_values(&[&self.label])
+                        .observe(latency.as_secs_f64());
+
+                    state.app().shards().set_latency(self.id, *latency);
+                }
+            },
+            Event::Ready(..) => state.app().shards().set_ready(self.id),
+            Event::Resumed => {
+                metrics.shard_resumes().with_label_values(&[&self.label]).inc();
+                state.app().shards().set_ready(self.id);
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Whether the event can be dropped if the work queue of
+/// the shard is full, with [`OverflowPolicy::DropLowPriority`].
+fn is_low_priority(event: &Event) -> bool {
+    !matches!(
+        event,
+        Event::InteractionCreate(..) | Event::Ready(..) | Event::Resumed
+    )
+}
+
+/// Why an event could not be sent to the work queue of a shard
+#[derive(Debug)]
+enum EnqueueError {
+    /// The queue is full and the event has low priority
+    Full(Event),
+    /// The queue is still full after waiting
+    Timeout(Event),
+    Closed,
+}
+
+/// Sends the event to the work queue following the overflow policy.
+async fn send(
+    queue: &mpsc::Sender<Event>,
+    event: Event,
+    policy: OverflowPolicy,
+    timeout: Duration,
+) -> std::result::Result<(), EnqueueError> {
+    if policy == OverflowPolicy::DropLowPriority && is_low_priority(&event) {
+        return queue.try_send(event).map_err(|error| match error {
+            TrySendError::Full(event) => EnqueueError::Full(event),
+            TrySendError::Closed(..) => EnqueueError::Closed,
+        });
+    }
+
+    // Waits until there's enough space in the queue, but not for
+    // too long since the shard cannot heartbeat in the meantime.
+    queue.send_timeout(event, timeout).await.map_err(|error| match error {
+        SendTimeoutError::Timeout(event) => EnqueueError::Timeout(event),
+        SendTimeoutError::Closed(..) => EnqueueError::Closed,
+    })
+}
+
+/// Sends the event to the work queue of the shard,
+/// following the configured overflow policy.
+async fn enqueue(
+    state: &State,
+    queue: &mpsc::Sender<Event>,
+    shard: &str,
+    event: Event,
+) {
+    let metrics = state.app().metrics();
+    let depth = metrics.event_queue_depth().with_label_values(&[shard]);
+    depth.inc();
+
+    let policy = state.config().events().overflow_policy();
+    let timeout = state.config().events().wait_timeout();
+    let Err(error) = send(queue, event, policy, timeout).await else {
+        return;
+    };
+
+    depth.dec();
+    match error {
+        EnqueueError::Full(event) => {
+            metrics.events_dropped().with_label_values(&[shard]).inc();
+            tracing::debug!(
+                kind = ?event.kind(),
+                "Event queue is full; dropping event"
+            );
+        },
+        EnqueueError::Timeout(event) => {
+            metrics.events_dropped().with_label_values(&[shard]).inc();
+            tracing::warn!(
+                kind = ?event.kind(),
+                ?timeout,
+                "Event queue is still full after waiting; dropping event"
+            );
+        },
+        EnqueueError::Closed => {
+            tracing::warn!("Event queue is closed; dropping event");
+        },
+    }
+}
+
+/// Takes events from the work queue of a shard and processes
+/// them, as long as there are permits available.
+async fn worker(
+    state: State,
+    mut queue: mpsc::Receiver<Event>,
+    tracker: TaskTracker,
+    shard: String,
+) {
+    let metrics = state.app().metrics();
+    while let Some(event) = queue.recv().await {
+        metrics.event_queue_depth().with_label_values(&[&shard]).dec();
+
+        let permit = state
+            .event_permits()
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("event permits should not be closed");
+
+        let state = state.clone();
+        tracker.spawn(
+            async move {
+                let kind = format!("{:?}", event.kind());
+                let now = Instant::now();
+                if let Err(error) = process_event(state.clone(), event).await {
+                    tracing::error!(?error, "Failed to process event");
+                }
+
+                state
+                    .app()
+                    .metrics()
+                    .event_handle_duration()
+                    .with_label_values(&[&kind])
+                    .observe(now.elapsed().as_secs_f64());
+
+                drop(permit);
+            }
+            .in_current_span(),
+        );
+    }
+}
+
+/// Same as [`Shard::next_event`], but it passes raw gateway
+/// events to the recorder before parsing them.
+async fn next_event(
+    shard: &mut Shard,
+    recorder: Option<&Recorder>,
+) -> std::result::Result<Event, ReceiveMessageError> {
+    loop {
+        let json = match shard.next_message().await? {
+            Message::Close(frame) => return Ok(Event::GatewayClose(frame)),
+            Message::Text(json) => json,
+        };
+
+        if let Some(recorder) = recorder {
+            recorder.record(shard.id().number(), &json);
+        }
+
+        match twilight_gateway::parse(json, shard.config().event_types()) {
+            Ok(Some(event)) => return Ok(event.into()),
+            Ok(None) => {},
+            Err(error) => {
+                tracing::warn!(?error, "Failed to parse gateway event");
+            },
+        }
+    }
+}
+
+/// Runs the event loop of a shard until the process shuts down
+/// or the generation of the shard retires.
+///
+/// It returns an error if the shard has fatally closed, leaving
+/// the decision whether to restart the shard to the caller.
+///
+/// Otherwise, it returns the gateway session of the shard if
+/// saving sessions upon shutdown is enabled. `resume_url` is the
+/// resume URL of the saved session the shard was built with.
 #[tracing::instrument(skip_all, fields(id = %shard.id()))]
-pub async fn shard(state: State, shard: &mut Shard) {
+pub async fn shard(
+    state: State,
+    shard: &mut Shard,
+    generation: Arc<Generation>,
+    mut resume_url: Option<String>,
+) -> std::result::Result<Option<ShardSession>, ReceiveMessageError> {
     let tracker = TaskTracker::new();
+    let mut fatal_error = None;
+
+    let mut metrics = ShardMetrics::new(shard, generation.clone());
+    metrics.update_status(&state, shard.status().into());
+
+    let label = metrics.label.clone();
+    let queue_size = state.config().events().queue_size().get();
+    let (queue, receiver) = mpsc::channel(queue_size);
+    tracker.spawn(
+        worker(state.clone(), receiver, tracker.clone(), label.clone())
+            .in_current_span(),
+    );
 
     loop {
         tokio::select! {
-            result = shard.next_event() => {
+            result = next_event(shard, state.recorder()) => {
                 let event = match result {
                     Ok(event) => event,
                     Err(source) => {
+                        metrics.update_status(&state, shard.status().into());
                         if source.is_fatal() {
                             tracing::error!(?source, "Got fatal shard message error");
-                            state.app().perform_shutdown("Fatal error given from a shard");
+                            fatal_error = Some(source);
                             break;
                         }
                         tracing::warn!(?source, "Got shard message error");
                         continue;
                     },
                 };
-                state.songbird().process(&event).await;
-                state.app().metrics().events_processed().add(1);
-
-                if matches!(event, Event::GatewayHello(..) | Event::GatewayHeartbeatAck) {
-                    let latency = shard.latency().average().unwrap_or_default();
-                    state
-                        .app()
-                        .metrics()
-                        .shard_latency()
-                        .set(latency.as_secs_f64());
+                if let Event::Ready(info) = &event {
+                    resume_url = Some(info.resume_gateway_url.clone());
                 }
 
-                let state = state.clone();
-                tracker.spawn(
-                    async move {
-                        if let Err(error) = process_event(state, event).await {
-                            tracing::error!(?error, "Failed to process event");
-                        }
-                    }
-                    .in_current_span(),
-                );
+                metrics.record(&state, shard, &event);
+
+                // Shards of an inactive generation serve the same
+                // guilds as the active one, which handles them instead
+                if generation.is_active() {
+                    state.cache().update(&event);
+                    state.voice().process(&event).await;
+                    enqueue(&state, &queue, &label, event).await;
+                }
             },
             _ = state.app().shutdown_signal() => {
                 break;
             },
+            _ = generation.retired() => {
+                tracing::info!("Retiring shard...");
+                break;
+            },
         }
     }
 
-    if !shard.status().is_disconnected() {
+    // Closing the connection normally invalidates the session, which
+    // is not what we want if we're going to resume it later.
+    let save_session =
+        state.config().sessions().path().is_some() && generation.is_active();
+    let session = shard.session().cloned();
+
+    // Fatally closed shards have no connection left to close
+    let status = shard.status();
+    if !status.is_disconnected() && !status.is_fatally_closed() {
         tracing::info!("Disconnecting shard...");
 
-        if let Err(error) = shard.close(CloseFrame::NORMAL).await {
+        let frame =
+            if save_session { CloseFrame::RESUME } else { CloseFrame::NORMAL };
+
+        if let Err(error) = shard.close(frame).await {
             tracing::error!(?error, "Failed to close shard connection");
         }
 
@@ -168,8 +515,116 @@ pub async fn shard(state: State, shard: &mut Shard) {
         }
     }
 
+    // Lets the worker process the remaining events in the queue
+    drop(queue);
+
+    metrics.update_status(&state, shard.status().into());
     if tracker.close() {
         tracing::info!("Waiting for all tasks to be completed");
         tracker.wait().await;
     }
+
+    if let Some(error) = fatal_error {
+        return Err(error);
+    }
+
+    let session =
+        session.filter(|_| save_session).map(|session| ShardSession {
+            shard: shard.id().number(),
+            id: session.id().to_string(),
+            resume_url,
+            sequence: session.sequence(),
+        });
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{send, EnqueueError};
+    use crate::config::OverflowPolicy;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::Instant;
+    use twilight_gateway::Event;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Work queue with no space left
+    fn full_queue() -> (mpsc::Sender<Event>, mpsc::Receiver<Event>) {
+        let (queue, receiver) = mpsc::channel(1);
+        queue.try_send(Event::GatewayHeartbeatAck).unwrap();
+        (queue, receiver)
+    }
+
+    /// Takes an event from the queue after half of the wait timeout
+    fn make_space(mut receiver: mpsc::Receiver<Event>) {
+        tokio::spawn(async move {
+            tokio::time::sleep(TIMEOUT / 2).await;
+            receiver.recv().await;
+            receiver
+        });
+    }
+
+    #[tokio::test]
+    async fn test_drop_low_priority() {
+        tokio::time::pause();
+        let (queue, _receiver) = full_queue();
+
+        let started_at = Instant::now();
+        let event = Event::GatewayHeartbeatAck;
+        let result =
+            send(&queue, event, OverflowPolicy::DropLowPriority, TIMEOUT).await;
+
+        assert!(matches!(result, Err(EnqueueError::Full(..))));
+        assert_eq!(started_at.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_drop_low_priority_waits_for_others() {
+        tokio::time::pause();
+        let (queue, receiver) = full_queue();
+        make_space(receiver);
+
+        let event = Event::Resumed;
+        let result =
+            send(&queue, event, OverflowPolicy::DropLowPriority, TIMEOUT).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_wait() {
+        tokio::time::pause();
+        let (queue, receiver) = full_queue();
+        make_space(receiver);
+
+        let event = Event::GatewayHeartbeatAck;
+        let result = send(&queue, event, OverflowPolicy::Wait, TIMEOUT).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_wait_timeout() {
+        tokio::time::pause();
+        let (queue, _receiver) = full_queue();
+
+        let started_at = Instant::now();
+        let event = Event::Resumed;
+        let result = send(&queue, event, OverflowPolicy::Wait, TIMEOUT).await;
+
+        assert!(matches!(result, Err(EnqueueError::Timeout(Event::Resumed))));
+        assert!(started_at.elapsed() >= TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_closed_queue() {
+        let (queue, receiver) = mpsc::channel(1);
+        drop(receiver);
+
+        for policy in [OverflowPolicy::Wait, OverflowPolicy::DropLowPriority] {
+            let event = Event::GatewayHeartbeatAck;
+            let result = send(&queue, event, policy, TIMEOUT).await;
+            assert!(matches!(result, Err(EnqueueError::Closed)));
+        }
+    }
 }
@@ -0,0 +1,82 @@
+use error_stack::{Report, Result, ResultExt};
+use thiserror::Error;
+use twilight_gateway::Intents;
+use twilight_model::oauth::{Application, ApplicationFlags};
+
+use crate::{config, SetupError};
+
+/// Songbird relies on voice state and voice server update
+/// events to establish voice connections.
+const VOICE_INTENTS: Intents =
+    Intents::GUILDS.union(Intents::GUILD_VOICE_STATES);
+
+/// Privileged intents with their corresponding application flags
+/// whether they're enabled in the Discord developer portal.
+const PRIVILEGED_INTENTS: [(Intents, ApplicationFlags); 3] = [
+    (
+        Intents::GUILD_MEMBERS,
+        ApplicationFlags::GATEWAY_GUILD_MEMBERS
+            .union(ApplicationFlags::GATEWAY_GUILD_MEMBERS_LIMITED),
+    ),
+    (
+        Intents::GUILD_PRESENCES,
+        ApplicationFlags::GATEWAY_PRESENCE
+            .union(ApplicationFlags::GATEWAY_PRESENCE_LIMITED),
+    ),
+    (
+        Intents::MESSAGE_CONTENT,
+        ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+            .union(ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED),
+    ),
+];
+
+#[derive(Debug, Error)]
+#[error("{feature} requires missing gateway intents: {missing:?}")]
+struct MissingIntents {
+    feature: &'static str,
+    missing: Intents,
+}
+
+/// Gateway intents needed by every feature of the bot.
+fn requirements(cfg: &config::Shard) -> [(&'static str, Intents); 2] {
+    [("Voice", VOICE_INTENTS), ("Cache", cfg.cache().required_intents())]
+}
+
+/// Makes sure that configured gateway intents satisfy all of the
+/// required intents from every feature of the bot.
+///
+/// It also warns if any privileged intents are configured but
+/// not enabled for the bot application.
+pub fn check(
+    cfg: &config::Shard,
+    info: &Application,
+) -> Result<(), SetupError> {
+    let configured = cfg.gateway_intents();
+    for (feature, needed) in requirements(cfg) {
+        let missing = needed.difference(configured);
+        if !missing.is_empty() {
+            return Err(Report::new(MissingIntents { feature, missing }))
+                .attach_printable_lazy(|| {
+                    format!("GATEWAY_INTENTS: {configured:?}")
+                })
+                .change_context(SetupError);
+        }
+    }
+
+    let Some(flags) = info.flags else {
+        tracing::debug!(
+            "Application flags are not available; skipping privileged intents check"
+        );
+        return Ok(());
+    };
+
+    for (intent, flag) in PRIVILEGED_INTENTS {
+        if configured.contains(intent) && !flags.intersects(flag) {
+            tracing::warn!(
+                "Privileged intent {intent:?} is configured but not enabled for this bot application. Discord may refuse to connect any shards!"
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,60 +1,125 @@
 mod cmd;
+mod generation;
 mod handler;
+mod intents;
+mod presence;
+mod recorder;
+pub mod replay;
+mod reshard;
+mod session;
+mod session_limit;
 mod state;
+mod supervisor;
+mod voice;
 
 pub use cmd::{RunError, Runner};
+pub use recorder::{RecordedEvent, Recorder};
 pub use state::State;
 
+use crate::coordinator::{Assignment, CoordinatorClient};
+use crate::queue::LimitedQueue;
 use crate::BotQueue;
 use crate::{config, App, SetupError};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use error_stack::{Result, ResultExt};
+use generation::Generation;
 use kyoka::perform_request;
-use songbird::Songbird;
-use tokio::task::JoinSet;
-use twilight_gateway::Intents;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use twilight_cache_inmemory::InMemoryCache;
 use twilight_gateway::Shard;
 use twilight_gateway_queue::{LargeBotQueue, Queue};
 use twilight_http::Client as Http;
 use twilight_model::id::marker::UserMarker;
+use twilight_model::oauth::Application;
+use voice::Voice;
 
-#[must_use]
-fn default_gateway_intents() -> Intents {
-    Intents::GUILDS | Intents::GUILD_MESSAGES
+/// Creates new shards sharing the same gateway config and queue
+/// as the shards created upon startup.
+#[derive(Debug)]
+struct ShardFactory {
+    config: twilight_gateway::Config,
+    queue: Arc<dyn Queue>,
+}
+
+impl ShardFactory {
+    fn create(&self, total: u64) -> Vec<Shard> {
+        twilight_gateway::stream::create_range(
+            0..total,
+            total,
+            self.config.clone(),
+            |_, builder| builder.queue(self.queue.clone()).build(),
+        )
+        .collect()
+    }
 }
 
 async fn init_shards(
     cfg: &config::Shard,
     http: &Arc<Http>,
-) -> Result<Vec<Shard>, SetupError> {
+    info: &Application,
+    assignment: Option<&Assignment>,
+    app: &App,
+) -> Result<(Vec<Shard>, HashMap<u64, String>, ShardFactory), SetupError> {
     let mut gateway_cfg = twilight_gateway::Config::builder(
         cfg.bot().token().into(),
-        default_gateway_intents(),
+        cfg.gateway_intents(),
     );
 
     if let Some(proxy_url) = cfg.bot().gateway_proxy_url() {
         gateway_cfg = gateway_cfg.proxy_url(proxy_url.into());
     }
 
-    let gateway_cfg = gateway_cfg.build();
     let gateway_connect_info =
         perform_request!(http.gateway().authed(), SetupError).await?;
 
+    let limit = &gateway_connect_info.session_start_limit;
+    let limiter = app.session_limiter();
+    limiter.update(limit);
+    tracing::info!(
+        remaining = %limit.remaining,
+        total = %limit.total,
+        reset_after = ?std::time::Duration::from_millis(limit.reset_after),
+        floor = %cfg.session_limit().floor(),
+        "Received session start limit"
+    );
+
     let (id, amount, total) = match cfg.connect_amount() {
         config::ShardConnectAmount::Manual { id, amount, total } => {
             (*id, amount.get(), total.get())
         },
+        config::ShardConnectAmount::Coordinator => {
+            let assignment =
+                assignment.expect("coordinator should assign shards");
+            (assignment.id, assignment.amount, assignment.total)
+        },
         config::ShardConnectAmount::UseRecommended => {
             tracing::debug!("Getting recommended amount of shards...");
             (0, gateway_connect_info.shards, gateway_connect_info.shards)
         },
     };
 
+    if let Some(presence) = presence::initial(cfg.presence().settings(), total)
+    {
+        gateway_cfg = gateway_cfg.presence(presence);
+    }
+    let gateway_cfg = gateway_cfg.build();
+
     tracing::info!("Setting up gateway queue...");
     let queue: Arc<dyn Queue> = if let Some(queue_url) = cfg.gateway_queue_url()
     {
-        Arc::new(BotQueue::new(queue_url).await?)
+        let queue = BotQueue::new(
+            queue_url,
+            cfg.gateway_queue_secret(),
+            info.id,
+            cfg.gateway_queue_timeout(),
+            *cfg.gateway_queue_retry(),
+            app.clone(),
+        )
+        .await?;
+        Arc::new(queue)
     } else {
         let buckets = gateway_connect_info
             .session_start_limit
@@ -65,6 +130,9 @@ async fn init_shards(
         let queue = LargeBotQueue::new(buckets, http.clone()).await;
         Arc::new(queue)
     };
+    let floor = cfg.session_limit().floor();
+    let queue: Arc<dyn Queue> =
+        Arc::new(LimitedQueue::new(queue, limiter.clone(), floor));
 
     tracing::debug!(
         id = %id,
@@ -76,19 +144,53 @@ async fn init_shards(
     let min = id;
     let max = id + amount;
 
+    let sessions = session::load(cfg.sessions(), total).await;
+    let has_proxy = cfg.bot().gateway_proxy_url().is_some();
+    let factory =
+        ShardFactory { config: gateway_cfg.clone(), queue: queue.clone() };
     let shards = twilight_gateway::stream::create_range(
         min..max,
-        amount,
+        total,
         gateway_cfg,
-        |_, builder| builder.queue(queue.clone()).build(),
+        |id, mut builder| {
+            if let Some(saved) = sessions.get(&id.number()) {
+                tracing::debug!(shard.id = %id, "Resuming saved gateway session");
+                builder = builder.session(saved.session());
+
+                // Shards connect to the proxy URL until they receive
+                // `Ready`, so the saved resume URL takes its place
+                // unless the gateway is behind a proxy.
+                if let Some(resume_url) = saved.resume_url.as_ref() {
+                    if !has_proxy {
+                        builder = builder.proxy_url(resume_url.clone());
+                    }
+                }
+            }
+            builder.queue(queue.clone()).build()
+        },
     )
     .collect::<Vec<_>>();
 
-    Ok(shards)
+    let resume_urls = sessions
+        .into_values()
+        .filter_map(|saved| Some((saved.shard, saved.resume_url?)))
+        .collect();
+
+    Ok((shards, resume_urls, factory))
+}
+
+struct Bot {
+    state: State,
+    shards: Vec<Shard>,
+    /// Resume URLs of saved gateway sessions for each shard
+    resume_urls: HashMap<u64, String>,
+    factory: ShardFactory,
+    coordinator: Option<CoordinatorClient>,
+    heartbeats: Option<JoinHandle<()>>,
 }
 
 #[tracing::instrument(skip(app))]
-async fn init(app: App) -> Result<(State, Vec<Shard>), SetupError> {
+async fn init(app: App) -> Result<Bot, SetupError> {
     let cfg = config::Shard::from_env().change_context(SetupError)?;
     tracing::info!("Starting Discord bot client");
 
@@ -97,6 +199,8 @@ async fn init(app: App) -> Result<(State, Vec<Shard>), SetupError> {
     let info =
         perform_request!(http.current_user_application(), SetupError).await?;
 
+    intents::check(&cfg, &info)?;
+
     if cfg.bot().reload_commands_on_start() {
         tracing::info!(
             "Reload commands on start is enabled; reloading all commands"
@@ -106,44 +210,146 @@ async fn init(app: App) -> Result<(State, Vec<Shard>), SetupError> {
             .change_context(SetupError)?;
     }
 
-    let shards = init_shards(&cfg, &http).await?;
-    let clusters = songbird::shards::TwilightMap::new({
-        let mut map = std::collections::HashMap::new();
-        for shard in shards.iter() {
-            map.insert(shard.id().number(), shard.sender());
-        }
-        map
+    let coordinator = match (cfg.connect_amount(), cfg.gateway_queue_url()) {
+        (config::ShardConnectAmount::Coordinator, Some(queue_url)) => {
+            tracing::info!("Asking the coordinator which shards to connect");
+            let secret = cfg.gateway_queue_secret();
+            Some(CoordinatorClient::register(queue_url, secret).await?)
+        },
+        _ => None,
+    };
+
+    // The lease of the shard range has to be kept alive while
+    // the shards are being set up, which may take a while.
+    let heartbeats = coordinator.clone().map(|coordinator| {
+        let app = app.clone();
+        tokio::spawn(async move { coordinator.run_heartbeats(app).await })
     });
 
-    let songbird =
-        Songbird::twilight(clusters.into(), info.id.cast::<UserMarker>());
+    let assignment = coordinator.as_ref().map(CoordinatorClient::assignment);
+    let (shards, resume_urls, factory) =
+        init_shards(&cfg, &http, &info, assignment, &app).await?;
+    if let Some(total) = shards.first().map(|shard| shard.id().total()) {
+        app.shards().set_total(total);
+    }
+
+    let voice = Voice::new(app.shards().clone(), info.id.cast::<UserMarker>());
 
-    let state = State::new(app, cfg, http, info, songbird);
-    Ok((state, shards))
+    let cache = InMemoryCache::builder()
+        .resource_types(cfg.cache().resource_types())
+        .build();
+
+    let recorder = Recorder::new(cfg.recorder()).change_context(SetupError)?;
+    let state = State::new(app, cache, cfg, http, info, recorder, voice);
+    Ok(Bot { state, shards, resume_urls, factory, coordinator, heartbeats })
 }
 
 pub async fn start(app: App) -> Result<(), SetupError> {
     let mut handle = JoinSet::new();
-    let (state, shards) = init(app.clone()).await?;
+    let Bot {
+        state,
+        shards,
+        mut resume_urls,
+        factory,
+        coordinator,
+        heartbeats,
+    } = init(app.clone()).await?;
+
     tracing::info!("Starting bot with {} shard/s", shards.len());
 
-    for mut shard in shards {
-        let state = state.clone();
-        handle.spawn(async move {
-            handler::shard(state, &mut shard).await;
-        });
+    let total = shards.first().map(|shard| shard.id().total());
+
+    app.presence().init(state.config().presence().settings().clone());
+    let limit_refresh = tokio::spawn(session_limit::refresh(state.clone()));
+    let rotation = tokio::spawn(presence::rotate(state.clone()));
+
+    let generation = Generation::new(total.unwrap_or_default(), true);
+    for shard in shards {
+        let resume_url = resume_urls.remove(&shard.id().number());
+        handle.spawn(supervisor::run(
+            state.clone(),
+            shard,
+            generation.clone(),
+            resume_url,
+        ));
     }
 
-    tokio::select! {
-        _ = kyoka::util::shutdown_signal() => {
-            app.perform_shutdown("Received shutdown signal");
-        },
-        _ = app.shutdown_signal() => {}
-    };
+    // Resharding only works if this process has every shard
+    let (spawner, mut spawns) = mpsc::unbounded_channel();
+    let can_reshard = matches!(
+        state.config().connect_amount(),
+        config::ShardConnectAmount::UseRecommended
+    );
 
-    tracing::info!("Waiting for all shards to finish their tasks");
-    while handle.join_next().await.is_some() {}
+    if state.config().reshard().enabled() && can_reshard {
+        tokio::spawn(reshard::run(
+            state.clone(),
+            factory,
+            generation.clone(),
+            spawner,
+        ));
+    } else {
+        drop((factory, spawner));
+    }
+
+    let mut generations = vec![generation];
+    loop {
+        tokio::select! {
+            _ = kyoka::util::shutdown_signal() => {
+                app.perform_shutdown("Received shutdown signal");
+                break;
+            },
+            _ = app.shutdown_signal() => break,
+            Some(spawn) = spawns.recv() => {
+                tracing::info!(
+                    "Starting {} shard/s for resharding",
+                    spawn.shards.len()
+                );
+                for shard in spawn.shards {
+                    handle.spawn(supervisor::run(
+                        state.clone(),
+                        shard,
+                        spawn.generation.clone(),
+                        None,
+                    ));
+                }
+                generations.push(spawn.generation);
+            },
+        };
+    }
 
+    tracing::info!("Waiting for all shards to finish their tasks");
+    let mut sessions = Vec::new();
+    while let Some(result) = handle.join_next().await {
+        if let Ok(Some(session)) = result {
+            sessions.push(session);
+        }
+    }
     tracing::info!("All shards are successfully shut down");
+
+    // Shards must be disconnected before other processes take them
+    if let Some(coordinator) = coordinator {
+        if let Some(heartbeats) = heartbeats {
+            _ = heartbeats.await;
+        }
+        coordinator.unregister().await;
+    }
+    _ = rotation.await;
+    _ = limit_refresh.await;
+
+    // Only shards in the active generation give their sessions
+    let total = generations
+        .iter()
+        .find(|generation| generation.is_active())
+        .map(|generation| generation.total());
+
+    if let Some(total) = total.filter(|_| !sessions.is_empty()) {
+        if let Err(error) =
+            session::save(state.config().sessions(), total, sessions).await
+        {
+            tracing::error!(?error, "Failed to save gateway sessions");
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,71 @@
+use tokio::time::{Instant, MissedTickBehavior};
+use twilight_model::gateway::payload::outgoing::update_presence::UpdatePresencePayload;
+use twilight_model::gateway::payload::outgoing::UpdatePresence;
+
+use super::State;
+use crate::presence::{PresenceSettings, PresenceStats};
+
+async fn stats(state: &State, shard_count: u64) -> PresenceStats {
+    PresenceStats {
+        guild_count: state.cache().stats().guilds(),
+        playing_count: state.voice().playing_count().await,
+        shard_count,
+    }
+}
+
+/// Presence to identify shards with, showing the first activity.
+///
+/// Placeholders other than `{shard_count}` are filled in with
+/// zeros since nothing is received from the gateway yet.
+#[must_use]
+pub fn initial(
+    settings: &PresenceSettings,
+    shard_count: u64,
+) -> Option<UpdatePresencePayload> {
+    let stats = PresenceStats { shard_count, ..Default::default() };
+    let activity = settings.activities.first()?.render(&stats);
+    UpdatePresencePayload::new(vec![activity], false, None, settings.status)
+        .ok()
+}
+
+/// Rotates activities of the presence across all shards in this
+/// process on an interval, until the process shuts down.
+///
+/// The presence is sent immediately once it has been changed.
+/// The shard count is read from the active generation every time
+/// so it stays correct after resharding.
+pub async fn rotate(state: State) {
+    let period = state.config().presence().rotate_interval();
+    let mut interval =
+        tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // The first activity is already sent upon identifying
+    let mut index = 1;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = state.app().presence().changed() => {
+                index = 0;
+                interval.reset();
+            },
+            _ = state.app().shutdown_signal() => break,
+        }
+
+        let settings = state.app().presence().get();
+        if settings.activities.is_empty() {
+            continue;
+        }
+
+        let template = &settings.activities[index % settings.activities.len()];
+        index = index.wrapping_add(1);
+
+        let stats = stats(&state, state.app().shards().total()).await;
+        let activity = template.render(&stats);
+        match UpdatePresence::new(vec![activity], false, None, settings.status)
+        {
+            Ok(presence) => state.app().shards().send_presence(&presence),
+            Err(error) => tracing::warn!(?error, "Failed to build presence"),
+        }
+    }
+}
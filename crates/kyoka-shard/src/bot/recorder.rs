@@ -0,0 +1,365 @@
+use error_stack::{Result, ResultExt};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use twilight_model::gateway::event::GatewayEventDeserializer;
+
+use crate::config;
+
+/// A single line of a recording file
+#[derive(Debug, Deserialize)]
+pub struct RecordedEvent {
+    pub shard: u64,
+    /// Milliseconds since the Unix epoch
+    pub received_at: u64,
+    pub payload: Box<RawValue>,
+}
+
+#[derive(Debug)]
+struct Record {
+    shard: u64,
+    received_at: u64,
+    payload: String,
+}
+
+#[derive(Debug, Error)]
+#[error("Failed to start gateway event recorder")]
+pub struct RecorderError;
+
+/// How many records can be waiting to be written
+/// before the recorder starts dropping them.
+const BUFFER_SIZE: usize = 4096;
+
+/// Writes raw gateway events received from all shards into
+/// rotating JSONL files, in a dedicated thread.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    events: Option<Arc<HashSet<String>>>,
+    sender: SyncSender<Record>,
+}
+
+impl Recorder {
+    /// Starts recording if it is enabled in the configuration.
+    pub fn new(cfg: &config::Recorder) -> Result<Option<Self>, RecorderError> {
+        let Some(dir) = cfg.dir() else {
+            return Ok(None);
+        };
+
+        std::fs::create_dir_all(dir)
+            .change_context(RecorderError)
+            .attach_printable_lazy(|| format!("dir: {}", dir.display()))?;
+
+        let (sender, receiver) = mpsc::sync_channel(BUFFER_SIZE);
+        let writer = Writer {
+            dir: dir.to_path_buf(),
+            file: None,
+            max_file_size: cfg.max_file_size().get(),
+            max_files: cfg.max_files().get(),
+        };
+
+        std::thread::Builder::new()
+            .name("kyoka-recorder".into())
+            .spawn(move || writer.run(receiver))
+            .change_context(RecorderError)?;
+
+        tracing::info!(dir = %dir.display(), "Recording gateway events");
+        Ok(Some(Self { events: cfg.events().cloned().map(Arc::new), sender }))
+    }
+
+    /// Records a raw gateway event if its type is wanted.
+    ///
+    /// Non-dispatch events like heartbeats are only recorded if
+    /// every gateway event is recorded.
+    pub fn record(&self, shard: u64, payload: &str) {
+        if let Some(events) = &self.events {
+            let wanted = GatewayEventDeserializer::from_json(payload)
+                .and_then(|v| v.event_type().map(|kind| events.contains(kind)))
+                .unwrap_or_default();
+
+            if !wanted {
+                return;
+            }
+        }
+
+        let record = Record {
+            shard,
+            received_at: unix_millis(),
+            payload: payload.to_string(),
+        };
+
+        match self.sender.try_send(record) {
+            Ok(()) => {},
+            Err(TrySendError::Full(..)) => {
+                tracing::warn!("Recorder cannot keep up; dropping event");
+            },
+            Err(TrySendError::Disconnected(..)) => {
+                tracing::warn!("Recorder has stopped; dropping event");
+            },
+        }
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+struct Writer {
+    dir: PathBuf,
+    /// Current recording file and how many bytes are written to it
+    file: Option<(BufWriter<File>, u64)>,
+    max_file_size: u64,
+    max_files: usize,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<Record>) {
+        while let Ok(record) = receiver.recv() {
+            self.write_or_log(&record);
+
+            // Writes everything already waiting before flushing
+            while let Ok(record) = receiver.try_recv() {
+                self.write_or_log(&record);
+            }
+
+            if let Some((file, ..)) = &mut self.file {
+                if let Err(error) = file.flush() {
+                    tracing::warn!(?error, "Failed to flush recording file");
+                }
+            }
+        }
+    }
+
+    fn write_or_log(&mut self, record: &Record) {
+        if let Err(error) = self.write(record) {
+            tracing::warn!(?error, "Failed to record gateway event");
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> std::io::Result<()> {
+        // Discord sends compact JSON, but we want to make sure
+        // every record stays in a single line anyway.
+        let line = format!(
+            "{{\"shard\":{},\"received_at\":{},\"payload\":{}}}\n",
+            record.shard,
+            record.received_at,
+            record.payload.replace('\n', " ")
+        );
+
+        let size = line.len() as u64;
+        let rotate = match &self.file {
+            Some((.., written)) => written + size > self.max_file_size,
+            None => true,
+        };
+
+        if rotate {
+            self.rotate()?;
+        }
+
+        let (file, written) = self.file.as_mut().expect("rotated file");
+        file.write_all(line.as_bytes())?;
+        *written += size;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if let Some((mut file, ..)) = self.file.take() {
+            file.flush()?;
+        }
+
+        // Files rotated within the same millisecond must not
+        // be appended to the file they're rotated from.
+        let mut timestamp = unix_millis();
+        let path = loop {
+            let path = self.dir.join(format!("events-{timestamp:020}.jsonl"));
+            if !path.exists() {
+                break path;
+            }
+            timestamp += 1;
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        self.file = Some((BufWriter::new(file), written));
+
+        tracing::debug!(path = %path.display(), "Rotated recording file");
+        self.prune()
+    }
+
+    /// Removes the oldest recording files beyond the limit
+    fn prune(&self) -> std::io::Result<()> {
+        let mut files = recording_files(&self.dir)?;
+        if files.len() <= self.max_files {
+            return Ok(());
+        }
+
+        let excess = files.len() - self.max_files;
+        for path in files.drain(..excess) {
+            std::fs::remove_file(&path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lists recording files in the directory from the oldest one
+pub fn recording_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_recording =
+            path.file_name().and_then(|v| v.to_str()).is_some_and(|name| {
+                name.starts_with("events-") && name.ends_with(".jsonl")
+            });
+
+        if is_recording {
+            files.push(path);
+        }
+    }
+
+    // File names contain zero-padded timestamps
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{recording_files, Record, RecordedEvent, Recorder, Writer};
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::sync::Arc;
+
+    const HEARTBEAT_ACK: &str = r#"{"op":11,"d":null}"#;
+    const INTERACTION_CREATE: &str =
+        r#"{"op":0,"s":2,"t":"INTERACTION_CREATE","d":{}}"#;
+    const TYPING_START: &str = r#"{"op":0,"s":3,"t":"TYPING_START","d":{}}"#;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let name = format!("kyoka-recorder-{name}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn writer(dir: &Path, max_file_size: u64, max_files: usize) -> Writer {
+        Writer { dir: dir.to_path_buf(), file: None, max_file_size, max_files }
+    }
+
+    fn record(payload: &str) -> Record {
+        Record { shard: 1, received_at: 42, payload: payload.to_string() }
+    }
+
+    fn recorded(payloads: &[&str], events: Option<&[&str]>) -> Vec<String> {
+        let (sender, receiver) = mpsc::sync_channel(16);
+        let events = events.map(|events| {
+            let events = events.iter().map(ToString::to_string);
+            Arc::new(events.collect::<HashSet<_>>())
+        });
+
+        let recorder = Recorder { events, sender };
+        for payload in payloads {
+            recorder.record(0, payload);
+        }
+        drop(recorder);
+
+        receiver.iter().map(|record| record.payload).collect()
+    }
+
+    fn read_lines(path: &Path) -> Vec<RecordedEvent> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_record_every_event() {
+        let payloads = [HEARTBEAT_ACK, INTERACTION_CREATE, TYPING_START];
+        assert_eq!(recorded(&payloads, None), payloads);
+    }
+
+    #[test]
+    fn test_record_wanted_events() {
+        let payloads = [HEARTBEAT_ACK, INTERACTION_CREATE, TYPING_START];
+        let events = ["INTERACTION_CREATE"];
+        assert_eq!(recorded(&payloads, Some(&events)), [INTERACTION_CREATE]);
+    }
+
+    #[test]
+    fn test_write_readable_lines() {
+        let dir = temp_dir("lines");
+        let mut writer = writer(&dir, 1024 * 1024, 2);
+        writer.write(&record(INTERACTION_CREATE)).unwrap();
+        writer.write(&record("{\n\"op\":11,\n\"d\":null}")).unwrap();
+        drop(writer);
+
+        let files = recording_files(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+
+        let lines = read_lines(&files[0]);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].shard, 1);
+        assert_eq!(lines[0].received_at, 42);
+        assert_eq!(lines[0].payload.get(), INTERACTION_CREATE);
+    }
+
+    #[test]
+    fn test_rotate_and_prune() {
+        let dir = temp_dir("rotate");
+
+        // Every record is too large to share a file with another one
+        let size = INTERACTION_CREATE.len() as u64 + 64;
+        let mut writer = writer(&dir, size, 2);
+        writer.write(&record(HEARTBEAT_ACK)).unwrap();
+        writer.write(&record(INTERACTION_CREATE)).unwrap();
+        writer.write(&record(TYPING_START)).unwrap();
+        drop(writer);
+
+        let files = recording_files(&dir).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(read_lines(&files[0])[0].payload.get(), INTERACTION_CREATE);
+        assert_eq!(read_lines(&files[1])[0].payload.get(), TYPING_START);
+    }
+
+    #[test]
+    fn test_prune_oldest_files() {
+        let dir = temp_dir("prune");
+        for timestamp in 1..=4 {
+            let name = format!("events-{timestamp:020}.jsonl");
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        writer(&dir, 1024, 2).prune().unwrap();
+
+        let names = recording_files(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into())
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            names,
+            [
+                format!("events-{:020}.jsonl", 3),
+                format!("events-{:020}.jsonl", 4),
+            ]
+        );
+        assert!(dir.join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,321 @@
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse};
+use error_stack::{Result, ResultExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_gateway::error::ReceiveMessageError;
+use twilight_gateway::{Event, EventTypeFlags};
+use twilight_model::id::marker::UserMarker;
+use twilight_model::oauth::Application;
+
+use super::handler::process_event;
+use super::recorder::{recording_files, RecordedEvent};
+use super::voice::Voice;
+use super::State;
+use crate::{config, App};
+
+#[derive(Debug, Error)]
+#[error("Failed to replay gateway events")]
+pub struct ReplayError;
+
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    /// Events fed through the event handler
+    pub events: u64,
+    /// Events the event handler failed to process
+    pub failed: u64,
+    /// Requests received by the mock HTTP backend
+    pub requests: u64,
+}
+
+#[tracing::instrument(skip_all)]
+async fn mock_request(
+    req: HttpRequest,
+    body: Bytes,
+    requests: web::Data<AtomicU64>,
+) -> HttpResponse {
+    requests.fetch_add(1, Ordering::Relaxed);
+    tracing::info!(
+        method = %req.method(),
+        path = %req.path(),
+        body = %String::from_utf8_lossy(&body),
+        "Received HTTP request"
+    );
+    mock_response(req.path(), &body)
+}
+
+/// Responds to the Discord REST API routes used by the event
+/// handler like Discord would, with as little data as possible.
+fn mock_response(path: &str, body: &[u8]) -> HttpResponse {
+    let route = path.trim_start_matches("/api/v10");
+    if route.starts_with("/interactions/") && route.ends_with("/callback") {
+        return HttpResponse::NoContent().finish();
+    }
+
+    if route.starts_with("/webhooks/") && route.contains("/messages/") {
+        let content = serde_json::from_slice::<serde_json::Value>(body)
+            .ok()
+            .and_then(|v| v.get("content")?.as_str().map(ToString::to_string))
+            .unwrap_or_default();
+
+        return HttpResponse::Ok().json(mock_message(&content));
+    }
+
+    HttpResponse::NotFound().json(serde_json::json!({
+        "code": 0,
+        "message": "404: Not Found",
+    }))
+}
+
+/// Message sent by the bot in response to an interaction
+fn mock_message(content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "attachments": [],
+        "author": {
+            "avatar": null,
+            "bot": true,
+            "discriminator": "0000",
+            "id": "1",
+            "username": "Kyoka (replay)",
+        },
+        "channel_id": "1",
+        "components": [],
+        "content": content,
+        "edited_timestamp": null,
+        "embeds": [],
+        "id": "1",
+        "mention_everyone": false,
+        "mention_roles": [],
+        "mentions": [],
+        "pinned": false,
+        "timestamp": "2024-01-01T00:00:00.000000+00:00",
+        "tts": false,
+        "type": 0,
+    })
+}
+
+/// Application info given to the event handler, since
+/// there's no Discord to retrieve it from.
+fn mock_application() -> Result<Application, ReplayError> {
+    let info = serde_json::json!({
+        "bot_public": false,
+        "bot_require_code_grant": false,
+        "description": "",
+        "flags": 0,
+        "icon": null,
+        "id": "1",
+        "name": "Kyoka (replay)",
+        "rpc_origins": [],
+        "summary": "",
+        "verify_key": "",
+    });
+    serde_json::from_value(info).change_context(ReplayError)
+}
+
+/// Feeds recorded gateway events from a recording file, or every
+/// recording file in a directory, through the event handler.
+///
+/// Discord REST API requests made by the event handler are sent to
+/// a mock HTTP backend which logs them and gives minimal responses,
+/// so events can be replayed without connecting to Discord.
+pub async fn run(path: &Path) -> Result<ReplaySummary, ReplayError> {
+    let cfg = config::Shard::from_env().change_context(ReplayError)?;
+    let files = if path.is_dir() {
+        recording_files(path)
+            .change_context(ReplayError)
+            .attach_printable_lazy(|| format!("dir: {}", path.display()))?
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let requests = web::Data::new(AtomicU64::new(0));
+    let server = actix_web::HttpServer::new({
+        let requests = requests.clone();
+        move || {
+            actix_web::App::new()
+                .app_data(requests.clone())
+                .default_service(web::to(mock_request))
+        }
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .change_context(ReplayError)?;
+
+    let addr = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    let server = tokio::spawn(server);
+    tracing::info!("Mock HTTP backend is listening at http://{addr}");
+
+    let http = twilight_http::Client::builder()
+        .token(cfg.bot().token().into())
+        .proxy(addr.to_string(), true)
+        .ratelimiter(None)
+        .build();
+
+    let info = mock_application()?;
+
+    let cache = InMemoryCache::builder()
+        .resource_types(cfg.cache().resource_types())
+        .build();
+
+    let app = App::new().change_context(ReplayError)?;
+    let voice = Voice::new(app.shards().clone(), info.id.cast::<UserMarker>());
+    let state = State::new(app, cache, cfg, Arc::new(http), info, None, voice);
+
+    let mut summary = ReplaySummary::default();
+    for file in files {
+        replay_file(&state, &file, &mut summary).await?;
+    }
+
+    handle.stop(true).await;
+    _ = server.await;
+
+    summary.requests = requests.load(Ordering::Relaxed);
+    Ok(summary)
+}
+
+/// Parses the payload of a recorded event like shards do
+fn parse_event(
+    recorded: &RecordedEvent,
+) -> std::result::Result<Option<Event>, ReceiveMessageError> {
+    let payload = recorded.payload.get().to_string();
+    let event = twilight_gateway::parse(payload, EventTypeFlags::all())?;
+    Ok(event.map(Event::from))
+}
+
+#[tracing::instrument(skip(state, summary))]
+async fn replay_file(
+    state: &State,
+    file: &Path,
+    summary: &mut ReplaySummary,
+) -> Result<(), ReplayError> {
+    let content = tokio::fs::read_to_string(file)
+        .await
+        .change_context(ReplayError)
+        .attach_printable_lazy(|| format!("file: {}", file.display()))?;
+
+    tracing::info!("Replaying gateway events from {}", file.display());
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let recorded = serde_json::from_str::<RecordedEvent>(line)
+            .change_context(ReplayError)
+            .attach_printable_lazy(|| {
+                format!("line {} of {}", index + 1, file.display())
+            })?;
+
+        let event = match parse_event(&recorded) {
+            Ok(Some(event)) => event,
+            Ok(None) => continue,
+            Err(error) => {
+                tracing::warn!(
+                    ?error,
+                    line = %(index + 1),
+                    "Failed to parse recorded gateway event"
+                );
+                continue;
+            },
+        };
+
+        state.cache().update(&event);
+        summary.events += 1;
+
+        // Events are processed one by one to keep the replay deterministic
+        if let Err(error) = process_event(state.clone(), event).await {
+            tracing::error!(
+                ?error,
+                shard.id = %recorded.shard,
+                line = %(index + 1),
+                "Failed to process recorded event"
+            );
+            summary.failed += 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mock_application, mock_message, mock_response, parse_event};
+    use crate::bot::recorder::RecordedEvent;
+    use actix_web::body::MessageBody;
+    use actix_web::http::StatusCode;
+    use twilight_gateway::Event;
+    use twilight_model::channel::Message;
+
+    fn recorded(line: &str) -> RecordedEvent {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn test_mock_application() {
+        assert!(mock_application().is_ok());
+    }
+
+    #[test]
+    fn test_mock_interaction_callback() {
+        let response =
+            mock_response("/api/v10/interactions/1/token/callback", b"{}");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn test_mock_update_response() {
+        let path = "/api/v10/webhooks/1/token/messages/@original";
+        let response = mock_response(path, br#"{"content":"Joined!"}"#);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().try_into_bytes().unwrap();
+        let message = serde_json::from_slice::<Message>(&body).unwrap();
+        assert_eq!(message.content, "Joined!");
+    }
+
+    #[test]
+    fn test_mock_unknown_route() {
+        let response = mock_response("/api/v10/guilds/1", b"");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_mock_message() {
+        let message = mock_message("Pong!");
+        assert!(serde_json::from_value::<Message>(message).is_ok());
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let line =
+            r#"{"shard":3,"received_at":42,"payload":{"op":11,"d":null}}"#;
+        let recorded = recorded(line);
+        assert_eq!(recorded.shard, 3);
+        assert!(matches!(
+            parse_event(&recorded),
+            Ok(Some(Event::GatewayHeartbeatAck))
+        ));
+
+        let line = r#"{"shard":0,"received_at":42,"payload":{"op":0,"s":1,"t":"RESUMED","d":{}}}"#;
+        assert!(matches!(
+            parse_event(&recorded(line)),
+            Ok(Some(Event::Resumed))
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid_event() {
+        let line = r#"{"shard":0,"received_at":42,"payload":{"op":0,"s":1,"t":"GUILD_DELETE","d":{}}}"#;
+        assert!(parse_event(&recorded(line)).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_line() {
+        let line = r#"{"shard":0,"payload":{"op":11,"d":null}}"#;
+        assert!(serde_json::from_str::<RecordedEvent>(line).is_err());
+    }
+}
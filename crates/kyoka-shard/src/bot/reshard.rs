@@ -0,0 +1,280 @@
+use error_stack::{Report, Result, ResultExt};
+use kyoka::perform_request;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::{Instant, MissedTickBehavior};
+use twilight_gateway::Shard;
+use twilight_model::id::marker::ChannelMarker;
+use twilight_model::id::Id;
+
+use super::generation::Generation;
+use super::{ShardFactory, State};
+use crate::shards::Shards;
+
+/// How often new shards are checked whether all of them are ready
+const READY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+#[error("Failed to reshard")]
+pub struct ReshardError;
+
+/// Shards of a new generation to be run by the bot
+pub struct Spawn {
+    pub generation: Arc<Generation>,
+    pub shards: Vec<Shard>,
+}
+
+/// Whether the recommended amount of shards has grown enough
+/// from the current amount to reshard.
+fn should_reshard(current: u64, recommended: u64, threshold: f64) -> bool {
+    #[allow(clippy::cast_precision_loss)]
+    let grown = recommended as f64 >= current as f64 * threshold;
+    recommended > current && grown
+}
+
+fn record(state: &State, decision: &str) {
+    state
+        .app()
+        .metrics()
+        .reshard_decisions()
+        .with_label_values(&[decision])
+        .inc();
+}
+
+fn set_shard_count(state: &State, kind: &str, count: u64) {
+    state
+        .app()
+        .metrics()
+        .shard_count()
+        .with_label_values(&[kind])
+        .set(i64::try_from(count).unwrap_or(i64::MAX));
+}
+
+/// Checks Discord's recommended amount of shards on an interval
+/// and reshards once it has grown enough, until the process
+/// shuts down.
+pub async fn run(
+    state: State,
+    factory: ShardFactory,
+    mut current: Arc<Generation>,
+    spawner: UnboundedSender<Spawn>,
+) {
+    let period = state.config().reshard().check_interval();
+    let mut interval =
+        tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    set_shard_count(&state, "current", current.total());
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = state.app().shutdown_signal() => break,
+        }
+
+        let info = match perform_request!(
+            state.http().gateway().authed(),
+            ReshardError
+        )
+        .await
+        {
+            Ok(info) => info,
+            Err(error) => {
+                tracing::warn!(
+                    ?error,
+                    "Failed to get recommended amount of shards"
+                );
+                record(&state, "check_failed");
+                continue;
+            },
+        };
+
+        set_shard_count(&state, "recommended", info.shards);
+
+        let threshold = state.config().reshard().threshold();
+        if !should_reshard(current.total(), info.shards, threshold) {
+            record(&state, "skipped");
+            continue;
+        }
+
+        tracing::info!(
+            current = %current.total(),
+            recommended = %info.shards,
+            "Resharding to the recommended amount of shards"
+        );
+        record(&state, "started");
+
+        match reshard(&state, &factory, &current, info.shards, &spawner).await {
+            Ok(next) => {
+                tracing::info!(total = %next.total(), "Resharding completed");
+                current = next;
+                set_shard_count(&state, "current", current.total());
+                record(&state, "completed");
+            },
+            Err(error) => {
+                tracing::error!(?error, "Failed to reshard");
+                record(&state, "failed");
+            },
+        }
+    }
+}
+
+/// Waits until every shard of the generation is ready,
+/// returning `false` if it takes longer than `timeout`.
+async fn wait_until_ready(generation: &Generation, timeout: Duration) -> bool {
+    let ready = async {
+        while !generation.is_ready() {
+            tokio::time::sleep(READY_CHECK_INTERVAL).await;
+        }
+    };
+    tokio::time::timeout(timeout, ready).await.is_ok()
+}
+
+/// Makes the next generation the active one in place of the current.
+///
+/// Shards of the new generation may have been restarted while
+/// connecting, so their latest senders are taken from it.
+fn hand_over(shards: &Shards, current: &Generation, next: &Generation) {
+    current.set_active(false);
+    next.set_active(true);
+
+    for (id, sender) in next.senders() {
+        shards.set_sender(id, sender);
+    }
+    shards.set_total(next.total());
+}
+
+async fn reshard(
+    state: &State,
+    factory: &ShardFactory,
+    current: &Generation,
+    total: u64,
+    spawner: &UnboundedSender<Spawn>,
+) -> Result<Arc<Generation>, ReshardError> {
+    let next = Generation::new(total, false);
+    let shards = factory.create(total);
+    let spawn = Spawn { generation: next.clone(), shards };
+    if spawner.send(spawn).is_err() {
+        return Err(Report::new(ReshardError))
+            .attach_printable("Bot is shutting down");
+    }
+
+    // Events from the new shards are ignored until all of them
+    // are ready, so the current shards keep handling everything.
+    let timeout = state.config().reshard().ready_timeout();
+    tokio::select! {
+        ready = wait_until_ready(&next, timeout) => {
+            if !ready {
+                next.retire();
+                return Err(Report::new(ReshardError)).attach_printable(
+                    "New shards were not ready in time",
+                );
+            }
+        },
+        _ = state.app().shutdown_signal() => {
+            return Err(Report::new(ReshardError))
+                .attach_printable("Bot is shutting down");
+        },
+    }
+
+    hand_over(state.app().shards(), current, &next);
+
+    // Voice connections belong to the shard of their guild, so the
+    // existing calls have to be joined again through the new shards.
+    let mut channels = Vec::new();
+    for (guild_id, call) in state.voice().calls() {
+        if let Some(channel_id) = call.lock().await.current_channel() {
+            channels.push((guild_id, channel_id));
+        }
+    }
+
+    let rejoins =
+        channels.into_iter().map(|(guild_id, channel_id)| async move {
+            let channel_id = Id::<ChannelMarker>::from(channel_id.0);
+            if let Err(error) = state.voice().join(guild_id, channel_id).await {
+                tracing::warn!(
+                    ?error,
+                    guild.id = %guild_id,
+                    "Failed to rejoin voice channel after resharding"
+                );
+            }
+        });
+    futures::future::join_all(rejoins).await;
+
+    current.retire();
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hand_over, should_reshard, wait_until_ready};
+    use crate::bot::generation::Generation;
+    use crate::shards::Shards;
+    use std::time::Duration;
+    use twilight_gateway::{Intents, Shard, ShardId};
+    use twilight_model::id::Id;
+
+    #[tokio::test]
+    async fn test_wait_until_ready() {
+        tokio::time::pause();
+        let generation = Generation::new(2, false);
+        generation.mark_ready(0);
+
+        let marker = generation.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            marker.mark_ready(1);
+        });
+
+        let timeout = Duration::from_secs(60);
+        assert!(wait_until_ready(&generation, timeout).await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_ready_times_out() {
+        tokio::time::pause();
+        let generation = Generation::new(2, false);
+        generation.mark_ready(0);
+
+        let started_at = tokio::time::Instant::now();
+        let timeout = Duration::from_secs(60);
+        assert!(!wait_until_ready(&generation, timeout).await);
+        assert!(started_at.elapsed() >= timeout);
+    }
+
+    #[test]
+    fn test_hand_over() {
+        let shards = Shards::default();
+        let current = Generation::new(2, true);
+        let next = Generation::new(4, false);
+        for id in 0..4 {
+            let shard = Shard::new(
+                ShardId::new(id, 4),
+                "token".into(),
+                Intents::empty(),
+            );
+            next.set_sender(id, shard.sender());
+        }
+
+        // Guilds served by shard 3 of the new generation only
+        let guild_id = Id::new(3 << 22);
+        assert!(shards.sender_for_guild(guild_id).is_none());
+
+        hand_over(&shards, &current, &next);
+        assert!(!current.is_active());
+        assert!(next.is_active());
+        assert_eq!(shards.total(), 4);
+        assert!(shards.sender_for_guild(guild_id).is_some());
+    }
+
+    #[test]
+    fn test_should_reshard() {
+        assert!(!should_reshard(4, 4, 1.25));
+        assert!(!should_reshard(4, 3, 1.));
+        assert!(!should_reshard(4, 4, 1.));
+        assert!(should_reshard(4, 5, 1.));
+        assert!(!should_reshard(8, 9, 1.25));
+        assert!(should_reshard(8, 10, 1.25));
+    }
+}
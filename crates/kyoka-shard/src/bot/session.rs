@@ -0,0 +1,200 @@
+use error_stack::{Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use twilight_gateway::Session;
+
+use crate::config;
+
+/// Gateway session of a shard saved upon graceful shutdown, so
+/// it can be resumed after the process restarts instead of
+/// identifying again.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ShardSession {
+    pub shard: u64,
+    pub id: String,
+    pub resume_url: Option<String>,
+    pub sequence: u64,
+}
+
+impl ShardSession {
+    #[must_use]
+    pub fn session(&self) -> Session {
+        Session::new(self.sequence, self.id.clone())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SavedSessions {
+    /// Seconds since the Unix epoch
+    saved_at: u64,
+    total: u64,
+    shards: Vec<ShardSession>,
+}
+
+#[derive(Debug, Error)]
+#[error("Failed to save gateway sessions")]
+pub struct SaveSessionsError;
+
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Loads saved gateway sessions from the previous process if
+/// they're fresh enough and the total amount of shards is the same.
+///
+/// The file will be removed after loading so saved sessions
+/// cannot be resumed more than once.
+pub async fn load(
+    cfg: &config::Sessions,
+    total: u64,
+) -> HashMap<u64, ShardSession> {
+    let Some(path) = cfg.path() else {
+        return HashMap::new();
+    };
+
+    let content = match tokio::fs::read(path).await {
+        Ok(content) => content,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            return HashMap::new();
+        },
+        Err(error) => {
+            tracing::warn!(?error, "Failed to read saved gateway sessions");
+            return HashMap::new();
+        },
+    };
+
+    if let Err(error) = tokio::fs::remove_file(path).await {
+        tracing::warn!(?error, "Failed to remove saved gateway sessions");
+    }
+
+    let saved = match serde_json::from_slice::<SavedSessions>(&content) {
+        Ok(saved) => saved,
+        Err(error) => {
+            tracing::warn!(?error, "Failed to parse saved gateway sessions");
+            return HashMap::new();
+        },
+    };
+
+    let age = unix_now().saturating_sub(Duration::from_secs(saved.saved_at));
+    if age > cfg.max_age() {
+        tracing::info!(?age, "Saved gateway sessions are too old to resume");
+        return HashMap::new();
+    }
+
+    if saved.total != total {
+        tracing::info!(
+            saved.total = %saved.total,
+            total = %total,
+            "Total amount of shards has changed; not resuming saved gateway sessions"
+        );
+        return HashMap::new();
+    }
+
+    tracing::info!("Loaded {} saved gateway session/s", saved.shards.len());
+    saved.shards.into_iter().map(|session| (session.shard, session)).collect()
+}
+
+/// Saves gateway sessions of all shards in this process.
+pub async fn save(
+    cfg: &config::Sessions,
+    total: u64,
+    shards: Vec<ShardSession>,
+) -> Result<(), SaveSessionsError> {
+    let Some(path) = cfg.path() else {
+        return Ok(());
+    };
+
+    let amount = shards.len();
+    let saved = SavedSessions { saved_at: unix_now().as_secs(), total, shards };
+    let content =
+        serde_json::to_vec(&saved).change_context(SaveSessionsError)?;
+
+    tokio::fs::write(path, content)
+        .await
+        .change_context(SaveSessionsError)
+        .attach_printable_lazy(|| format!("path: {}", path.display()))?;
+
+    tracing::info!("Saved {amount} gateway session/s");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load, save, unix_now, SavedSessions, ShardSession};
+    use crate::config;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    fn path(name: &str) -> PathBuf {
+        let name = format!("kyoka-sessions-{name}-{}.json", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn session(shard: u64) -> ShardSession {
+        ShardSession {
+            shard,
+            id: format!("session-{shard}"),
+            resume_url: Some("wss://resume.discord.gg".into()),
+            sequence: 42,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let path = path("roundtrip");
+        let cfg = config::Sessions::new(Some(path.clone()), MAX_AGE);
+        save(&cfg, 2, vec![session(0), session(1)]).await.unwrap();
+
+        let sessions = load(&cfg, 2).await;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[&1].id, "session-1");
+        assert_eq!(
+            sessions[&1].resume_url.as_deref(),
+            Some("wss://resume.discord.gg")
+        );
+        assert_eq!(sessions[&1].sequence, 42);
+
+        // Saved sessions can only be resumed once
+        assert!(!path.exists());
+        assert!(load(&cfg, 2).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_stale_sessions() {
+        let path = path("stale");
+        let saved = SavedSessions {
+            saved_at: (unix_now() - MAX_AGE * 2).as_secs(),
+            total: 1,
+            shards: vec![session(0)],
+        };
+        std::fs::write(&path, serde_json::to_vec(&saved).unwrap()).unwrap();
+
+        let cfg = config::Sessions::new(Some(path.clone()), MAX_AGE);
+        assert!(load(&cfg, 1).await.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_load_with_different_total() {
+        let path = path("total");
+        let cfg = config::Sessions::new(Some(path.clone()), MAX_AGE);
+        save(&cfg, 2, vec![session(0), session(1)]).await.unwrap();
+
+        assert!(load(&cfg, 4).await.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_sessions() {
+        let cfg = config::Sessions::new(None, MAX_AGE);
+        save(&cfg, 1, vec![session(0)]).await.unwrap();
+        assert!(load(&cfg, 1).await.is_empty());
+    }
+}
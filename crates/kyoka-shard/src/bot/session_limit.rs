@@ -0,0 +1,35 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::State;
+
+/// Refreshes the session start limit of the bot on an interval,
+/// until the process shuts down.
+///
+/// Other processes of the same bot use up session starts too,
+/// so the tracked limit cannot be trusted for long.
+pub async fn refresh(state: State) {
+    let period = state.config().session_limit().refresh_interval();
+    let mut interval =
+        tokio::time::interval_at(Instant::now() + period, period);
+
+    let limiter = state.app().session_limiter();
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = state.app().shutdown_signal() => break,
+        }
+
+        match limiter.refresh(state.http()).await {
+            Ok(limit) => tracing::info!(
+                remaining = %limit.remaining,
+                total = %limit.total,
+                reset_after = ?Duration::from_millis(limit.reset_after),
+                "Refreshed session start limit"
+            ),
+            Err(error) => {
+                tracing::warn!(?error, "Failed to refresh session start limit");
+            },
+        }
+    }
+}
@@ -1,35 +1,54 @@
 use kyoka::util::Sensitive;
-use songbird::Songbird;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use tokio::sync::Semaphore;
+use twilight_cache_inmemory::InMemoryCache;
 use twilight_http::client::InteractionClient;
+use twilight_model::id::{marker::UserMarker, Id};
 use twilight_model::oauth::Application;
 
+use super::recorder::Recorder;
+use super::voice::Voice;
 use crate::{config, App};
 
 #[derive(Clone)]
 pub struct State {
     pub(super) app: App,
+    pub(super) cache: Arc<InMemoryCache>,
     pub(super) config: Arc<config::Shard>,
+    pub(super) event_permits: Arc<Semaphore>,
     pub(super) http: Arc<twilight_http::Client>,
     pub(super) info: Application,
-    pub(super) songbird: Arc<Songbird>,
+    pub(super) maintenance: Arc<AtomicBool>,
+    pub(super) recorder: Option<Recorder>,
+    pub(super) voice: Voice,
 }
 
 impl State {
     pub(super) fn new(
         app: App,
+        cache: InMemoryCache,
         config: config::Shard,
         http: Arc<twilight_http::Client>,
         info: Application,
-        songbird: Songbird,
+        recorder: Option<Recorder>,
+        voice: Voice,
     ) -> Self {
         Self {
             app: app.clone(),
+            cache: Arc::new(cache),
+            maintenance: Arc::new(AtomicBool::new(
+                config.maintenance().enabled(),
+            )),
+            event_permits: Arc::new(Semaphore::new(
+                config.events().concurrency().get(),
+            )),
             config: Arc::new(config),
             http,
             info,
-            songbird: Arc::new(songbird),
+            recorder,
+            voice,
         }
     }
 }
@@ -40,6 +59,20 @@ impl State {
         &self.app
     }
 
+    /// Gets the [`InMemoryCache`] object, updated from
+    /// every event received from all shards in this process.
+    #[must_use]
+    pub fn cache(&self) -> &InMemoryCache {
+        &self.cache
+    }
+
+    /// Permits to process events, limiting how many events
+    /// can be processed at the same time.
+    #[must_use]
+    pub fn event_permits(&self) -> &Arc<Semaphore> {
+        &self.event_permits
+    }
+
     /// Shows the application information of a Discord bot
     #[must_use]
     pub fn info(&self) -> &Application {
@@ -62,12 +95,51 @@ impl State {
         &self.http
     }
 
-    /// Gets the [`Songbird`] object.
-    pub fn songbird(&self) -> &Songbird {
-        &self.songbird
+    /// Gets the gateway event recorder, if recording is enabled.
+    #[must_use]
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Gets the voice calls of the bot.
+    #[must_use]
+    pub const fn voice(&self) -> &Voice {
+        &self.voice
+    }
+
+    /// Whether the bot is currently in maintenance mode
+    #[must_use]
+    pub fn in_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    /// Toggles maintenance mode while the bot is running
+    pub fn set_maintenance(&self, enabled: bool) {
+        let previous = self.maintenance.swap(enabled, Ordering::Relaxed);
+        if previous != enabled {
+            tracing::info!(%enabled, "Maintenance mode has been toggled");
+        }
+    }
+
+    /// Checks whether the user owns the bot application,
+    /// either directly or as a member of the owning team.
+    #[must_use]
+    pub fn is_owner(&self, user_id: Id<UserMarker>) -> bool {
+        is_owner(&self.info, user_id)
     }
 }
 
+fn is_owner(info: &Application, user_id: Id<UserMarker>) -> bool {
+    let is_app_owner =
+        info.owner.as_ref().is_some_and(|owner| owner.id == user_id);
+
+    let is_team_member = info.team.as_ref().is_some_and(|team| {
+        team.members.iter().any(|member| member.user.id == user_id)
+    });
+
+    is_app_owner || is_team_member
+}
+
 impl Debug for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         struct AppDebug<'a>(&'a Application);
@@ -87,6 +159,89 @@ impl Debug for State {
             .field("application", &AppDebug(&self.info))
             .field("config", &*self.config)
             .field("http", &Sensitive::new(()))
+            .field("maintenance", &self.in_maintenance())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::is_owner;
+    use serde_json::json;
+    use twilight_model::id::Id;
+    use twilight_model::oauth::Application;
+
+    fn user(id: u64) -> serde_json::Value {
+        json!({
+            "avatar": null,
+            "discriminator": "0",
+            "id": id.to_string(),
+            "username": format!("user-{id}"),
+        })
+    }
+
+    fn application(
+        owner: Option<u64>,
+        team_members: Option<&[u64]>,
+    ) -> Application {
+        let team = team_members.map(|ids| {
+            let members = ids
+                .iter()
+                .map(|id| {
+                    json!({
+                        "membership_state": 2,
+                        "permissions": ["*"],
+                        "role": "admin",
+                        "team_id": "10",
+                        "user": user(*id),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            json!({
+                "icon": null,
+                "id": "10",
+                "members": members,
+                "name": "Kyoka",
+                "owner_user_id": ids[0].to_string(),
+            })
+        });
+
+        serde_json::from_value(json!({
+            "bot_public": false,
+            "bot_require_code_grant": false,
+            "description": "",
+            "flags": 0,
+            "icon": null,
+            "id": "1",
+            "name": "Kyoka",
+            "owner": owner.map(user),
+            "rpc_origins": [],
+            "summary": "",
+            "team": team,
+            "verify_key": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_app_owner() {
+        let info = application(Some(2), None);
+        assert!(is_owner(&info, Id::new(2)));
+        assert!(!is_owner(&info, Id::new(3)));
+    }
+
+    #[test]
+    fn test_team_member() {
+        let info = application(None, Some(&[2, 3]));
+        assert!(is_owner(&info, Id::new(2)));
+        assert!(is_owner(&info, Id::new(3)));
+        assert!(!is_owner(&info, Id::new(4)));
+    }
+
+    #[test]
+    fn test_no_owner() {
+        let info = application(None, None);
+        assert!(!is_owner(&info, Id::new(2)));
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use twilight_gateway::error::ReceiveMessageErrorType;
+use twilight_gateway::Shard;
+use twilight_model::gateway::CloseCode;
+
+use super::generation::Generation;
+use super::session::ShardSession;
+use super::{handler, State};
+
+/// Delay before restarting a shard for the first time
+const BASE_DELAY: Duration = Duration::from_secs(2);
+
+/// Maximum delay between restarts of the same shard
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Shards running longer than this before failing will have
+/// their restart backoff reset back to [`BASE_DELAY`].
+const STABLE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Whether restarting the shard is pointless because Discord will
+/// refuse every new connection for the same configuration.
+fn is_unrecoverable(kind: &ReceiveMessageErrorType) -> bool {
+    matches!(
+        kind,
+        ReceiveMessageErrorType::FatallyClosed {
+            close_code: CloseCode::AuthenticationFailed
+                | CloseCode::InvalidApiVersion
+                | CloseCode::InvalidIntents
+                | CloseCode::DisallowedIntents
+                | CloseCode::InvalidShard
+                | CloseCode::ShardingRequired
+        }
+    )
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_DELAY)
+}
+
+/// Keeps track of the message sender of the shard in its generation,
+/// and in the shard registry as well if the generation is active.
+fn register_sender(state: &State, shard: &Shard, generation: &Generation) {
+    let id = shard.id().number();
+    generation.set_sender(id, shard.sender());
+    if generation.is_active() {
+        state.app().shards().set_sender(id, shard.sender());
+    }
+}
+
+/// Runs the shard and restarts it with exponential backoff
+/// whenever it fatally closes.
+///
+/// It only performs a shutdown on the entire process if
+/// the shard closed with an unrecoverable close code.
+#[tracing::instrument(skip_all, fields(id = %shard.id()))]
+pub async fn run(
+    state: State,
+    mut shard: Shard,
+    generation: Arc<Generation>,
+    mut resume_url: Option<String>,
+) -> Option<ShardSession> {
+    let label = shard.id().number().to_string();
+    let mut attempt = 0;
+    register_sender(&state, &shard, &generation);
+
+    loop {
+        let started_at = Instant::now();
+        let result = handler::shard(
+            state.clone(),
+            &mut shard,
+            generation.clone(),
+            resume_url.take(),
+        )
+        .await;
+
+        let error = match result {
+            Ok(session) => return session,
+            Err(..) if generation.is_retired() => return None,
+            Err(error) => error,
+        };
+
+        if is_unrecoverable(error.kind()) {
+            tracing::error!(?error, "Shard closed with an unrecoverable error");
+            state
+                .app()
+                .perform_shutdown("Unrecoverable error given from a shard");
+            return None;
+        }
+
+        if started_at.elapsed() >= STABLE_PERIOD {
+            attempt = 0;
+        }
+
+        let delay = backoff(attempt);
+        attempt = attempt.saturating_add(1);
+        tracing::warn!(?delay, %attempt, "Restarting shard in {delay:?}...");
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = state.app().shutdown_signal() => return None,
+            _ = generation.retired() => return None,
+        };
+
+        state
+            .app()
+            .metrics()
+            .shard_restarts()
+            .with_label_values(&[&label])
+            .inc();
+
+        // The new shard shares the same config including the gateway
+        // queue, so it has to wait for its turn before identifying.
+        shard = Shard::with_config(shard.id(), shard.config().clone());
+
+        // Voice calls look up the sender of their shard in the registry
+        // so they have to be pointed to the new shard.
+        register_sender(&state, &shard, &generation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, is_unrecoverable, BASE_DELAY, MAX_DELAY};
+    use twilight_gateway::error::ReceiveMessageErrorType;
+    use twilight_model::gateway::CloseCode;
+
+    fn closed(close_code: CloseCode) -> ReceiveMessageErrorType {
+        ReceiveMessageErrorType::FatallyClosed { close_code }
+    }
+
+    #[test]
+    fn test_is_unrecoverable() {
+        assert!(is_unrecoverable(&closed(CloseCode::AuthenticationFailed)));
+        assert!(is_unrecoverable(&closed(CloseCode::InvalidApiVersion)));
+        assert!(is_unrecoverable(&closed(CloseCode::InvalidIntents)));
+        assert!(is_unrecoverable(&closed(CloseCode::DisallowedIntents)));
+        assert!(is_unrecoverable(&closed(CloseCode::InvalidShard)));
+        assert!(is_unrecoverable(&closed(CloseCode::ShardingRequired)));
+
+        assert!(!is_unrecoverable(&ReceiveMessageErrorType::Io));
+        assert!(!is_unrecoverable(&ReceiveMessageErrorType::Deserializing {
+            event: String::new(),
+        }));
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(0), BASE_DELAY);
+        assert_eq!(backoff(1), BASE_DELAY * 2);
+        assert_eq!(backoff(3), BASE_DELAY * 8);
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        assert_eq!(backoff(20), MAX_DELAY);
+        assert_eq!(backoff(u32::MAX), MAX_DELAY);
+        assert!(backoff(6) < MAX_DELAY);
+        assert_eq!(backoff(8), MAX_DELAY);
+    }
+}
@@ -0,0 +1,189 @@
+use async_trait::async_trait;
+use songbird::error::{JoinError, JoinResult};
+use songbird::id::{ChannelId, GuildId};
+use songbird::shards::{Shard, VoiceUpdate};
+use songbird::tracks::PlayMode;
+use songbird::Call;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::Mutex;
+use twilight_gateway::Event;
+use twilight_model::gateway::payload::outgoing::UpdateVoiceState;
+use twilight_model::id::marker::{ChannelMarker, GuildMarker, UserMarker};
+use twilight_model::id::Id;
+
+use crate::shards::Shards;
+
+/// Sends voice state updates through the shard currently serving
+/// the guild, which is looked up from the shard registry every time
+/// so restarted and resharded shards are picked up.
+#[derive(Debug)]
+struct ShardSender {
+    shards: Shards,
+}
+
+#[async_trait]
+impl VoiceUpdate for ShardSender {
+    async fn update_voice_state(
+        &self,
+        guild_id: GuildId,
+        channel_id: Option<ChannelId>,
+        self_deaf: bool,
+        self_mute: bool,
+    ) -> JoinResult<()> {
+        let guild_id = Id::<GuildMarker>::from(guild_id.0);
+        let sender = self
+            .shards
+            .sender_for_guild(guild_id)
+            .ok_or(JoinError::NoSender)?;
+
+        let channel_id = channel_id.map(|id| Id::<ChannelMarker>::from(id.0));
+        let command =
+            UpdateVoiceState::new(guild_id, channel_id, self_deaf, self_mute);
+
+        sender.command(&command)?;
+        Ok(())
+    }
+}
+
+/// Voice calls of the bot in every guild.
+///
+/// It works like [`songbird::Songbird`], except that calls are not
+/// bound to the message sender of a shard at the time they were
+/// created, which breaks once the shard is replaced.
+#[derive(Debug, Clone)]
+pub struct Voice {
+    calls: Arc<StdMutex<HashMap<Id<GuildMarker>, Arc<Mutex<Call>>>>>,
+    sender: Arc<ShardSender>,
+    user_id: Id<UserMarker>,
+}
+
+impl Voice {
+    #[must_use]
+    pub fn new(shards: Shards, user_id: Id<UserMarker>) -> Self {
+        Self {
+            calls: Arc::default(),
+            sender: Arc::new(ShardSender { shards }),
+            user_id,
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, guild_id: Id<GuildMarker>) -> Option<Arc<Mutex<Call>>> {
+        let calls = self.calls.lock().expect("calls lock poisoned");
+        calls.get(&guild_id).cloned()
+    }
+
+    /// Gets the call of the guild, creating a new one if there's none.
+    /// It does not join any voice channel.
+    #[must_use]
+    pub fn get_or_insert(&self, guild_id: Id<GuildMarker>) -> Arc<Mutex<Call>> {
+        let mut calls = self.calls.lock().expect("calls lock poisoned");
+        calls
+            .entry(guild_id)
+            .or_insert_with(|| {
+                let shard = Shard::Generic(self.sender.clone());
+                Arc::new(Mutex::new(Call::new(guild_id, shard, self.user_id)))
+            })
+            .clone()
+    }
+
+    /// Every call of the bot, including calls which are not
+    /// connected to any voice channel.
+    #[must_use]
+    pub fn calls(&self) -> Vec<(Id<GuildMarker>, Arc<Mutex<Call>>)> {
+        let calls = self.calls.lock().expect("calls lock poisoned");
+        calls.iter().map(|(id, call)| (*id, call.clone())).collect()
+    }
+
+    /// Number of calls connected to a voice channel and
+    /// currently playing a track.
+    pub async fn playing_count(&self) -> usize {
+        let mut count = 0;
+        for (_, call) in self.calls() {
+            let track = {
+                let call = call.lock().await;
+                if call.current_connection().is_none() {
+                    continue;
+                }
+                call.queue().current()
+            };
+
+            let Some(track) = track else { continue };
+            if let Ok(info) = track.get_info().await {
+                if matches!(info.playing, PlayMode::Play) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Forgets the call of the guild if it is still the given one,
+    /// so a call created since then is kept.
+    fn remove(&self, guild_id: Id<GuildMarker>, call: &Arc<Mutex<Call>>) {
+        let mut calls = self.calls.lock().expect("calls lock poisoned");
+        if calls.get(&guild_id).is_some_and(|c| Arc::ptr_eq(c, call)) {
+            calls.remove(&guild_id);
+        }
+    }
+
+    /// Joins the voice channel, waiting until the connection to
+    /// the voice server is established.
+    ///
+    /// Events of the shard serving the guild must be processed
+    /// in another task for it to complete.
+    pub async fn join(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+    ) -> JoinResult<Arc<Mutex<Call>>> {
+        let call = self.get_or_insert(guild_id);
+        let join = call.lock().await.join(channel_id).await?;
+        join.await?;
+        Ok(call)
+    }
+
+    /// Leaves the voice channel of the guild and removes its call.
+    pub async fn leave(&self, guild_id: Id<GuildMarker>) -> JoinResult<()> {
+        let call =
+            self.calls.lock().expect("calls lock poisoned").remove(&guild_id);
+
+        if let Some(call) = call {
+            call.lock().await.leave().await?;
+        }
+        Ok(())
+    }
+
+    /// Passes voice state and voice server updates to their calls
+    pub async fn process(&self, event: &Event) {
+        match event {
+            Event::VoiceServerUpdate(update) => {
+                let Some(call) = self.get(update.guild_id) else { return };
+                if let Some(endpoint) = &update.endpoint {
+                    call.lock()
+                        .await
+                        .update_server(endpoint.clone(), update.token.clone());
+                }
+            },
+            Event::VoiceStateUpdate(update) => {
+                if update.0.user_id != self.user_id {
+                    return;
+                }
+
+                let Some(guild_id) = update.0.guild_id else { return };
+                let Some(call) = self.get(guild_id) else { return };
+                call.lock().await.update_state(
+                    update.0.session_id.clone(),
+                    update.0.channel_id,
+                );
+
+                // The bot left or was disconnected from the voice channel
+                if update.0.channel_id.is_none() {
+                    self.remove(guild_id, &call);
+                }
+            },
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use thiserror::Error;
+use twilight_cache_inmemory::ResourceType;
+use twilight_model::gateway::Intents;
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct Cache {
+    resource_types: ResourceType,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown cache resource type {0:?} in \"CACHE_RESOURCE_TYPES\"")]
+struct UnknownResourceType(String);
+
+const DEFAULT_RESOURCE_TYPES: ResourceType = ResourceType::GUILD
+    .union(ResourceType::CHANNEL)
+    .union(ResourceType::VOICE_STATE)
+    .union(ResourceType::MEMBER)
+    .union(ResourceType::ROLE);
+
+const RESOURCE_TYPES_SUGGESTION: &str = concat!(
+    "Suggestion: \"CACHE_RESOURCE_TYPES\" accepts a comma separated list of ",
+    "`guilds`, `channels`, `voice_states`, `members` and `roles`"
+);
+
+impl Cache {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let Some(value) =
+            env::var("CACHE_RESOURCE_TYPES").change_context(LoadError)?
+        else {
+            return Ok(Self { resource_types: DEFAULT_RESOURCE_TYPES });
+        };
+
+        let mut resource_types = ResourceType::empty();
+        for name in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            resource_types |= match name.to_lowercase().as_str() {
+                "guilds" => ResourceType::GUILD,
+                "channels" => ResourceType::CHANNEL,
+                "voice_states" => ResourceType::VOICE_STATE,
+                "members" => ResourceType::MEMBER,
+                "roles" => ResourceType::ROLE,
+                _ => {
+                    return Err(UnknownResourceType(name.to_string()))
+                        .attach_printable(RESOURCE_TYPES_SUGGESTION)
+                        .change_context(LoadError)
+                },
+            };
+        }
+
+        Ok(Self { resource_types })
+    }
+}
+
+impl Cache {
+    /// Resource types to be cached from incoming gateway events
+    #[must_use]
+    pub const fn resource_types(&self) -> ResourceType {
+        self.resource_types
+    }
+
+    /// Gateway intents needed to keep the cached resources up to date.
+    ///
+    /// Members are only expected to be cached from `GUILD_CREATE`
+    /// and voice state events, so `GUILD_MEMBERS` is not required.
+    #[must_use]
+    pub fn required_intents(&self) -> Intents {
+        let mut intents = Intents::empty();
+        if self.resource_types.intersects(
+            ResourceType::GUILD
+                | ResourceType::CHANNEL
+                | ResourceType::ROLE
+                | ResourceType::MEMBER,
+        ) {
+            intents |= Intents::GUILDS;
+        }
+        if self.resource_types.contains(ResourceType::VOICE_STATE) {
+            intents |= Intents::GUILD_VOICE_STATES;
+        }
+        intents
+    }
+}
@@ -0,0 +1,147 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+use super::LoadError;
+
+/// What to do with incoming events if the work queue
+/// of a shard is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Waits until there's enough space in the queue. It stops
+    /// reading events from the shard in the meantime, so the event
+    /// is dropped if it cannot be queued within the wait timeout.
+    Wait,
+    /// Drops low priority events (everything except interactions
+    /// and session events) and waits for the rest.
+    DropLowPriority,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Unknown overflow policy, expected `wait` or `drop`")]
+pub struct UnknownOverflowPolicy;
+
+impl FromStr for OverflowPolicy {
+    type Err = UnknownOverflowPolicy;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "wait" => Ok(Self::Wait),
+            "drop" => Ok(Self::DropLowPriority),
+            _ => Err(UnknownOverflowPolicy),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error(
+    "Event queue wait timeout must be at most {MAX_WAIT_TIMEOUT_SECS} second/s"
+)]
+pub struct WaitTimeoutTooLong;
+
+#[derive(Debug)]
+pub struct Events {
+    concurrency: NonZeroUsize,
+    overflow_policy: OverflowPolicy,
+    queue_size: NonZeroUsize,
+    wait_timeout: Duration,
+}
+
+const DEFAULT_CONCURRENCY: usize = 64;
+const DEFAULT_QUEUE_SIZE: usize = 256;
+const DEFAULT_WAIT_TIMEOUT_SECS: u64 = 1;
+
+/// Shards have to heartbeat within about 41 seconds, so they
+/// must not be held off for a large part of it.
+const MAX_WAIT_TIMEOUT_SECS: u64 = 10;
+
+fn wait_timeout(
+    secs: u64,
+) -> std::result::Result<Duration, WaitTimeoutTooLong> {
+    if secs > MAX_WAIT_TIMEOUT_SECS {
+        return Err(WaitTimeoutTooLong);
+    }
+    Ok(Duration::from_secs(secs.max(1)))
+}
+
+impl Events {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let concurrency = env::var_parse("EVENT_CONCURRENCY")
+            .change_context(LoadError)?
+            .unwrap_or(NonZeroUsize::new(DEFAULT_CONCURRENCY).unwrap());
+
+        let overflow_policy = env::var_parse("EVENT_OVERFLOW_POLICY")
+            .change_context(LoadError)?
+            .unwrap_or(OverflowPolicy::Wait);
+
+        let queue_size = env::var_parse("EVENT_QUEUE_SIZE")
+            .change_context(LoadError)?
+            .unwrap_or(NonZeroUsize::new(DEFAULT_QUEUE_SIZE).unwrap());
+
+        let wait_timeout = env::var_parse("EVENT_QUEUE_WAIT_TIMEOUT")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT_SECS);
+
+        let wait_timeout = wait_timeout(wait_timeout)
+            .attach_printable("while parsing EVENT_QUEUE_WAIT_TIMEOUT")
+            .change_context(LoadError)?;
+
+        Ok(Self { concurrency, overflow_policy, queue_size, wait_timeout })
+    }
+}
+
+impl Events {
+    /// Maximum amount of events being processed at the
+    /// same time across all shards in this process.
+    #[must_use]
+    pub const fn concurrency(&self) -> NonZeroUsize {
+        self.concurrency
+    }
+
+    #[must_use]
+    pub const fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Maximum amount of events waiting to be processed per shard
+    #[must_use]
+    pub const fn queue_size(&self) -> NonZeroUsize {
+        self.queue_size
+    }
+
+    /// How long to wait for space in the work queue of a shard
+    /// before dropping the event.
+    ///
+    /// Shards cannot send heartbeats while waiting, so it is
+    /// capped well below the heartbeat interval of the gateway.
+    #[must_use]
+    pub const fn wait_timeout(&self) -> Duration {
+        self.wait_timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wait_timeout, OverflowPolicy, WaitTimeoutTooLong};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_overflow_policy() {
+        assert_eq!("wait".parse(), Ok(OverflowPolicy::Wait));
+        assert_eq!(" DROP ".parse(), Ok(OverflowPolicy::DropLowPriority));
+        assert!("block".parse::<OverflowPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        assert_eq!(wait_timeout(0), Ok(Duration::from_secs(1)));
+        assert_eq!(wait_timeout(3), Ok(Duration::from_secs(3)));
+        assert_eq!(wait_timeout(10), Ok(Duration::from_secs(10)));
+        assert_eq!(wait_timeout(11), Err(WaitTimeoutTooLong));
+        assert_eq!(wait_timeout(60), Err(WaitTimeoutTooLong));
+    }
+}
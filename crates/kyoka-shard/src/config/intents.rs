@@ -0,0 +1,84 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use thiserror::Error;
+use twilight_model::gateway::Intents;
+
+use super::LoadError;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Unknown gateway intent {0:?} in \"GATEWAY_INTENTS\"")]
+struct UnknownIntent(String);
+
+const DEFAULT_INTENTS: Intents =
+    Intents::GUILDS.union(Intents::GUILD_VOICE_STATES);
+
+const INTENTS_SUGGESTION: &str = concat!(
+    "Suggestion: \"GATEWAY_INTENTS\" accepts a comma separated list of ",
+    "intent names in snake case (e.g. `guilds,guild_voice_states`)"
+);
+
+fn parse(value: &str) -> std::result::Result<Intents, UnknownIntent> {
+    let mut intents = Intents::empty();
+    for name in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+        intents |= match name.to_lowercase().as_str() {
+            "guilds" => Intents::GUILDS,
+            "guild_members" => Intents::GUILD_MEMBERS,
+            "guild_emojis_and_stickers" => Intents::GUILD_EMOJIS_AND_STICKERS,
+            "guild_integrations" => Intents::GUILD_INTEGRATIONS,
+            "guild_webhooks" => Intents::GUILD_WEBHOOKS,
+            "guild_invites" => Intents::GUILD_INVITES,
+            "guild_voice_states" => Intents::GUILD_VOICE_STATES,
+            "guild_presences" => Intents::GUILD_PRESENCES,
+            "guild_messages" => Intents::GUILD_MESSAGES,
+            "guild_message_reactions" => Intents::GUILD_MESSAGE_REACTIONS,
+            "guild_message_typing" => Intents::GUILD_MESSAGE_TYPING,
+            "direct_messages" => Intents::DIRECT_MESSAGES,
+            "direct_message_reactions" => Intents::DIRECT_MESSAGE_REACTIONS,
+            "direct_message_typing" => Intents::DIRECT_MESSAGE_TYPING,
+            "message_content" => Intents::MESSAGE_CONTENT,
+            "guild_scheduled_events" => Intents::GUILD_SCHEDULED_EVENTS,
+            "auto_moderation_configuration" => {
+                Intents::AUTO_MODERATION_CONFIGURATION
+            },
+            "auto_moderation_execution" => Intents::AUTO_MODERATION_EXECUTION,
+            _ => return Err(UnknownIntent(name.to_string())),
+        };
+    }
+    Ok(intents)
+}
+
+/// Loads gateway intents from `GATEWAY_INTENTS` environment variable.
+///
+/// It defaults to `GUILDS` and `GUILD_VOICE_STATES` if not set.
+#[track_caller]
+pub(super) fn from_env() -> Result<Intents, LoadError> {
+    let Some(value) = env::var("GATEWAY_INTENTS").change_context(LoadError)?
+    else {
+        return Ok(DEFAULT_INTENTS);
+    };
+
+    parse(&value).attach_printable(INTENTS_SUGGESTION).change_context(LoadError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, UnknownIntent};
+    use twilight_model::gateway::Intents;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("guilds,guild_voice_states"),
+            Ok(Intents::GUILDS | Intents::GUILD_VOICE_STATES)
+        );
+        assert_eq!(
+            parse(" GUILDS , message_content,"),
+            Ok(Intents::GUILDS | Intents::MESSAGE_CONTENT)
+        );
+        assert_eq!(parse(""), Ok(Intents::empty()));
+        assert_eq!(
+            parse("guilds,voice"),
+            Err(UnknownIntent("voice".to_string()))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct Maintenance {
+    enabled: bool,
+    message: String,
+}
+
+const DEFAULT_MESSAGE: &str = concat!(
+    "Kyoka is currently under maintenance. ",
+    "Please try again later!"
+);
+
+impl Maintenance {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let enabled = env::var_parse("MAINTENANCE_MODE")
+            .change_context(LoadError)?
+            .unwrap_or(false);
+
+        let message = env::var("MAINTENANCE_MESSAGE")
+            .change_context(LoadError)?
+            .unwrap_or_else(|| DEFAULT_MESSAGE.into());
+
+        Ok(Self { enabled, message })
+    }
+}
+
+impl Maintenance {
+    /// Whether maintenance mode is enabled upon startup
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Message to reply to users while maintenance mode is enabled
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
@@ -1,16 +1,20 @@
 use error_stack::{Result, ResultExt};
-use kyoka::util::env;
+use kyoka::util::{env, Sensitive};
 use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
 
 use super::LoadError;
 
 #[derive(Debug)]
 pub struct Metrics {
+    admin_token: Option<Sensitive<String>>,
     host: IpAddr,
     port: u16,
+    ready_tolerance: Duration,
 }
 
 const DEFAULT_PORT: u16 = 3421;
+const DEFAULT_READY_TOLERANCE_SECS: u64 = 30;
 const DEFAULT_HOST: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 
 impl Metrics {
@@ -23,6 +27,11 @@ impl Metrics {
 
     #[track_caller]
     pub fn from_env() -> Result<Self, LoadError> {
+        let admin_token = env::var("ADMIN_API_TOKEN")
+            .change_context(LoadError)?
+            .filter(|v| !v.is_empty())
+            .map(Sensitive::new);
+
         let host = env::var_parse("METRICS_HOST")
             .change_context(LoadError)?
             .unwrap_or(DEFAULT_HOST);
@@ -31,11 +40,24 @@ impl Metrics {
             .change_context(LoadError)?
             .unwrap_or(DEFAULT_PORT);
 
-        Ok(Self { host, port })
+        let ready_tolerance = env::var_parse("HEALTH_READY_TOLERANCE")
+            .change_context(LoadError)?
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(DEFAULT_READY_TOLERANCE_SECS));
+
+        Ok(Self { admin_token, host, port, ready_tolerance })
     }
 }
 
 impl Metrics {
+    /// Token required to access the admin API.
+    ///
+    /// The admin API is disabled if it is not set.
+    #[must_use]
+    pub fn admin_token(&self) -> Option<&str> {
+        self.admin_token.as_ref().map(Sensitive::as_str)
+    }
+
     #[must_use]
     pub const fn host(&self) -> IpAddr {
         self.host
@@ -45,4 +67,11 @@ impl Metrics {
     pub const fn port(&self) -> u16 {
         self.port
     }
+
+    /// How long a shard can be unhealthy before the
+    /// process is no longer considered ready
+    #[must_use]
+    pub const fn ready_tolerance(&self) -> Duration {
+        self.ready_tolerance
+    }
 }
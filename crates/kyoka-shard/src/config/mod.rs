@@ -1,7 +1,26 @@
+mod cache;
+mod events;
+mod intents;
+mod maintenance;
 mod metrics;
+mod presence;
+mod queue_retry;
+mod recorder;
+mod reshard;
+mod runtime;
+mod sessions;
 mod shard;
 
+pub use self::cache::Cache;
+pub use self::events::{Events, OverflowPolicy};
+pub use self::maintenance::Maintenance;
 pub use self::metrics::Metrics;
+pub use self::presence::Presence;
+pub use self::queue_retry::QueueRetry;
+pub use self::recorder::Recorder;
+pub use self::reshard::Reshard;
+pub use self::runtime::{Runtime, RuntimeFlavor};
+pub use self::sessions::Sessions;
 pub use self::shard::{Shard, ShardConnectAmount};
 
 pub use kyoka::config::*;
@@ -0,0 +1,83 @@
+use error_stack::{Report, Result, ResultExt};
+use kyoka::util::env;
+use std::time::Duration;
+use thiserror::Error;
+use twilight_model::gateway::presence::Status;
+
+use super::LoadError;
+use crate::presence::{ActivityTemplate, PresenceSettings};
+
+#[derive(Debug)]
+pub struct Presence {
+    settings: PresenceSettings,
+    rotate_interval: Duration,
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "Unknown presence status, expected `online`, `idle`, `dnd` or `invisible`"
+)]
+struct UnknownStatus;
+
+const DEFAULT_ROTATE_INTERVAL_SECS: u64 = 60;
+
+fn parse_status(value: &str) -> Result<Status, UnknownStatus> {
+    match value.trim().to_lowercase().as_str() {
+        "online" => Ok(Status::Online),
+        "idle" => Ok(Status::Idle),
+        "dnd" => Ok(Status::DoNotDisturb),
+        "invisible" => Ok(Status::Invisible),
+        _ => Err(Report::new(UnknownStatus)),
+    }
+}
+
+impl Presence {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let status = env::var("PRESENCE_STATUS")
+            .change_context(LoadError)?
+            .map(|v| parse_status(&v))
+            .transpose()
+            .change_context(LoadError)?
+            .unwrap_or(Status::Online);
+
+        // Activities are separated with `|`, like:
+        // `watching:{guild_count} servers|listening:{playing_count} streams`
+        let activities = env::var("PRESENCE_ACTIVITIES")
+            .change_context(LoadError)?
+            .map(|v| {
+                v.split('|')
+                    .filter(|v| !v.trim().is_empty())
+                    .map(str::parse::<ActivityTemplate>)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+            })
+            .transpose()
+            .change_context(LoadError)
+            .attach_printable("while parsing PRESENCE_ACTIVITIES")?
+            .unwrap_or_default();
+
+        let rotate_interval = env::var_parse("PRESENCE_ROTATE_INTERVAL")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_ROTATE_INTERVAL_SECS);
+
+        Ok(Self {
+            settings: PresenceSettings { status, activities },
+            rotate_interval: Duration::from_secs(rotate_interval.max(1)),
+        })
+    }
+}
+
+impl Presence {
+    /// Presence to start the bot with. The bot keeps the
+    /// default presence if there are no activities set.
+    #[must_use]
+    pub const fn settings(&self) -> &PresenceSettings {
+        &self.settings
+    }
+
+    /// How long each activity is shown before moving on to the next one
+    #[must_use]
+    pub const fn rotate_interval(&self) -> Duration {
+        self.rotate_interval
+    }
+}
@@ -0,0 +1,100 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use std::time::Duration;
+
+use super::LoadError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueueRetry {
+    attempts: u32,
+    base_delay: Duration,
+    give_up_after: Duration,
+    max_delay: Duration,
+    probe_interval: Duration,
+}
+
+const DEFAULT_ATTEMPTS: u32 = 3;
+const DEFAULT_BASE_DELAY_SECS: u64 = 1;
+const DEFAULT_GIVE_UP_AFTER_SECS: u64 = 15 * 60;
+const DEFAULT_MAX_DELAY_SECS: u64 = 30;
+const DEFAULT_PROBE_INTERVAL_SECS: u64 = 30;
+
+impl QueueRetry {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let attempts = env::var_parse("GATEWAY_QUEUE_RETRIES")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_ATTEMPTS);
+
+        let base_delay = env::var_parse("GATEWAY_QUEUE_RETRY_BASE_DELAY")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_BASE_DELAY_SECS);
+
+        let give_up_after = env::var_parse("GATEWAY_QUEUE_GIVE_UP_AFTER")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_GIVE_UP_AFTER_SECS);
+
+        let max_delay = env::var_parse("GATEWAY_QUEUE_RETRY_MAX_DELAY")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_MAX_DELAY_SECS);
+
+        let probe_interval = env::var_parse("GATEWAY_QUEUE_PROBE_INTERVAL")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_PROBE_INTERVAL_SECS);
+
+        let base_delay = Duration::from_secs(base_delay.max(1));
+        Ok(Self {
+            attempts,
+            base_delay,
+            give_up_after: Duration::from_secs(give_up_after.max(1)),
+            max_delay: Duration::from_secs(max_delay).max(base_delay),
+            probe_interval: Duration::from_secs(probe_interval.max(1)),
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) const fn new(
+        attempts: u32,
+        base_delay: Duration,
+        max_delay: Duration,
+        give_up_after: Duration,
+        probe_interval: Duration,
+    ) -> Self {
+        Self { attempts, base_delay, give_up_after, max_delay, probe_interval }
+    }
+}
+
+impl QueueRetry {
+    /// How many times a failed request to the gateway queue server
+    /// is retried before falling back to the local queue
+    #[must_use]
+    pub const fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Delay before the first retry, which doubles on every retry
+    #[must_use]
+    pub const fn base_delay(&self) -> Duration {
+        self.base_delay
+    }
+
+    /// How long a shard keeps waiting for its turn from the gateway
+    /// queue server, including the waits it asks for, before falling
+    /// back to the local queue
+    #[must_use]
+    pub const fn give_up_after(&self) -> Duration {
+        self.give_up_after
+    }
+
+    #[must_use]
+    pub const fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    /// How often the gateway queue server is checked while the
+    /// local queue is used, to switch back once it's healthy
+    #[must_use]
+    pub const fn probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+}
@@ -0,0 +1,79 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use std::collections::HashSet;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct Recorder {
+    dir: Option<PathBuf>,
+    events: Option<HashSet<String>>,
+    max_file_size: NonZeroU64,
+    max_files: NonZeroUsize,
+}
+
+const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 8;
+
+impl Recorder {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let dir = env::var("GATEWAY_RECORD_DIR")
+            .change_context(LoadError)?
+            .map(PathBuf::from);
+
+        let events = env::var("GATEWAY_RECORD_EVENTS")
+            .change_context(LoadError)?
+            .map(|v| {
+                v.split(',')
+                    .map(|kind| kind.trim().to_uppercase())
+                    .filter(|kind| !kind.is_empty())
+                    .collect::<HashSet<_>>()
+            })
+            .filter(|v| !v.is_empty());
+
+        let max_file_size = env::var_parse("GATEWAY_RECORD_MAX_FILE_SIZE")
+            .change_context(LoadError)?
+            .unwrap_or(NonZeroU64::new(DEFAULT_MAX_FILE_SIZE).unwrap());
+
+        let max_files = env::var_parse("GATEWAY_RECORD_MAX_FILES")
+            .change_context(LoadError)?
+            .unwrap_or(NonZeroUsize::new(DEFAULT_MAX_FILES).unwrap());
+
+        Ok(Self { dir, events, max_file_size, max_files })
+    }
+}
+
+impl Recorder {
+    /// Directory to write recorded gateway events into.
+    ///
+    /// Recording is disabled if it is not set.
+    #[must_use]
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Dispatch event types to record, like `INTERACTION_CREATE`.
+    ///
+    /// Every gateway event is recorded if it is not set.
+    #[must_use]
+    pub const fn events(&self) -> Option<&HashSet<String>> {
+        self.events.as_ref()
+    }
+
+    /// Size in bytes a recording file can grow to
+    /// before moving on to the next file
+    #[must_use]
+    pub const fn max_file_size(&self) -> NonZeroU64 {
+        self.max_file_size
+    }
+
+    /// How many recording files are kept before
+    /// removing the oldest ones
+    #[must_use]
+    pub const fn max_files(&self) -> NonZeroUsize {
+        self.max_files
+    }
+}
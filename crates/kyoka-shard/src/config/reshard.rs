@@ -0,0 +1,74 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use std::time::Duration;
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct Reshard {
+    check_interval: Duration,
+    enabled: bool,
+    ready_timeout: Duration,
+    threshold: f64,
+}
+
+const DEFAULT_CHECK_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_READY_TIMEOUT_SECS: u64 = 15 * 60;
+const DEFAULT_THRESHOLD: f64 = 1.25;
+
+impl Reshard {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let enabled = env::var_parse("RESHARD_ENABLED")
+            .change_context(LoadError)?
+            .unwrap_or(false);
+
+        let check_interval = env::var_parse("RESHARD_CHECK_INTERVAL")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_CHECK_INTERVAL_SECS);
+
+        let ready_timeout = env::var_parse("RESHARD_READY_TIMEOUT")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_READY_TIMEOUT_SECS);
+
+        let threshold = env::var_parse::<f64>("RESHARD_THRESHOLD")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_THRESHOLD);
+
+        Ok(Self {
+            check_interval: Duration::from_secs(check_interval.max(1)),
+            enabled,
+            ready_timeout: Duration::from_secs(ready_timeout.max(1)),
+            threshold: threshold.max(1.),
+        })
+    }
+}
+
+impl Reshard {
+    /// How often Discord's recommended amount of shards is checked
+    #[must_use]
+    pub const fn check_interval(&self) -> Duration {
+        self.check_interval
+    }
+
+    /// Whether resharding is done automatically. It only applies
+    /// if the recommended amount of shards is used.
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// How long to wait for every new shard to be ready before
+    /// giving up on resharding
+    #[must_use]
+    pub const fn ready_timeout(&self) -> Duration {
+        self.ready_timeout
+    }
+
+    /// How many times the current amount of shards the recommended
+    /// amount has to reach before resharding, like `1.25`
+    #[must_use]
+    pub const fn threshold(&self) -> f64 {
+        self.threshold
+    }
+}
@@ -0,0 +1,67 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use thiserror::Error;
+
+use super::LoadError;
+
+/// Flavor of the Tokio runtime running the shards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeFlavor {
+    /// Everything runs in a single thread
+    CurrentThread,
+    /// Tasks are spread across multiple worker threads
+    MultiThread,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown runtime flavor, expected `current` or `multi`")]
+pub struct UnknownRuntimeFlavor;
+
+impl FromStr for RuntimeFlavor {
+    type Err = UnknownRuntimeFlavor;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "current" => Ok(Self::CurrentThread),
+            "multi" => Ok(Self::MultiThread),
+            _ => Err(UnknownRuntimeFlavor),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Runtime {
+    flavor: RuntimeFlavor,
+    worker_threads: Option<NonZeroUsize>,
+}
+
+impl Runtime {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let flavor = env::var_parse("RUNTIME")
+            .change_context(LoadError)?
+            .unwrap_or(RuntimeFlavor::CurrentThread);
+
+        let worker_threads =
+            env::var_parse("TOKIO_WORKER_THREADS").change_context(LoadError)?;
+
+        Ok(Self { flavor, worker_threads })
+    }
+}
+
+impl Runtime {
+    #[must_use]
+    pub const fn flavor(&self) -> RuntimeFlavor {
+        self.flavor
+    }
+
+    /// Amount of worker threads for the multi-threaded runtime.
+    ///
+    /// Tokio uses the amount of CPU cores if it is not set.
+    #[must_use]
+    pub const fn worker_threads(&self) -> Option<NonZeroUsize> {
+        self.worker_threads
+    }
+}
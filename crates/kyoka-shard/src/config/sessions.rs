@@ -0,0 +1,50 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct Sessions {
+    path: Option<PathBuf>,
+    max_age: Duration,
+}
+
+const DEFAULT_MAX_AGE_SECS: u64 = 60;
+
+impl Sessions {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let path = env::var("GATEWAY_SESSIONS_PATH")
+            .change_context(LoadError)?
+            .map(PathBuf::from);
+
+        let max_age = env::var_parse("GATEWAY_SESSIONS_MAX_AGE")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_MAX_AGE_SECS);
+
+        Ok(Self { path, max_age: Duration::from_secs(max_age) })
+    }
+
+    #[cfg(test)]
+    pub(crate) const fn new(path: Option<PathBuf>, max_age: Duration) -> Self {
+        Self { path, max_age }
+    }
+}
+
+impl Sessions {
+    /// Path of the file to save gateway sessions upon graceful shutdown.
+    ///
+    /// Resuming sessions across restarts is disabled if it is not set.
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// How old saved gateway sessions can be to be resumed
+    #[must_use]
+    pub const fn max_age(&self) -> Duration {
+        self.max_age
+    }
+}
@@ -1,13 +1,18 @@
 use error_stack::{Report, Result, ResultExt};
-use kyoka::util::env;
+use kyoka::util::{env, Sensitive};
 use std::num::NonZeroU64;
+use std::time::Duration;
 use thiserror::Error;
+use twilight_model::gateway::Intents;
 
 use super::LoadError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShardConnectAmount {
     UseRecommended,
+    /// Asks the coordinator at `GATEWAY_QUEUE_URL` which
+    /// shards to connect in this process
+    Coordinator,
     Manual {
         id: u64,
         /// How many shards must be connected in a
@@ -21,18 +26,26 @@ pub enum ShardConnectAmount {
 impl ShardConnectAmount {
     #[track_caller]
     fn from_env() -> Result<Self, LoadError> {
+        let use_coordinator = env::var_parse("SHARD_USE_COORDINATOR")
+            .change_context(LoadError)?
+            .unwrap_or(false);
+
+        if use_coordinator {
+            return Ok(Self::Coordinator);
+        }
+
+        let use_hostname_ordinal = env::var_parse("SHARD_USE_HOSTNAME_ORDINAL")
+            .change_context(LoadError)?
+            .unwrap_or(false);
+
         let use_recommended = env::var_parse("SHARD_USE_RECOMMENDED")
             .change_context(LoadError)?
             .unwrap_or(true);
 
-        if use_recommended {
+        if use_recommended && !use_hostname_ordinal {
             return Ok(Self::UseRecommended);
         }
 
-        let id = env::required_var_parse("SHARD_ID")
-            .change_context(LoadError)
-            .attach_printable(RECOMMENDED_SUGGESTION)?;
-
         let total: NonZeroU64 = env::required_var_parse("SHARD_TOTAL")
             .change_context(LoadError)
             .attach_printable(RECOMMENDED_SUGGESTION)?;
@@ -41,6 +54,29 @@ impl ShardConnectAmount {
             .change_context(LoadError)?
             .unwrap_or(NonZeroU64::new(1).unwrap());
 
+        let id = if use_hostname_ordinal {
+            let hostname = hostname().unwrap_or_default();
+            let Some(ordinal) = parse_ordinal(&hostname) else {
+                return Err(InvalidShardConfig::NoHostnameOrdinal)
+                    .attach_printable_lazy(|| format!("HOSTNAME: {hostname:?}"))
+                    .change_context(LoadError);
+            };
+
+            // Each pod connects its own block of `SHARD_AMOUNT` shards
+            let Some(id) = ordinal.checked_mul(amount.get()) else {
+                return Err(InvalidShardConfig::IdTooBig)
+                    .attach_printable_lazy(|| format!("HOSTNAME: {hostname:?}"))
+                    .change_context(LoadError);
+            };
+
+            tracing::debug!(%hostname, %ordinal, "Derived SHARD_ID: {id}");
+            id
+        } else {
+            env::required_var_parse("SHARD_ID")
+                .change_context(LoadError)
+                .attach_printable(RECOMMENDED_SUGGESTION)?
+        };
+
         if amount.get() > total.get() {
             return Err(InvalidShardConfig::AmountGtTotal)
                 .attach_printable_lazy(|| format!("SHARD_ID: {id}"))
@@ -82,13 +118,43 @@ impl ShardConnectAmount {
     }
 }
 
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME").ok().or_else(|| {
+        std::fs::read_to_string("/etc/hostname")
+            .ok()
+            .map(|v| v.trim().to_string())
+    })
+}
+
+/// Parses the ordinal of a StatefulSet pod from its
+/// hostname, like `3` from `kyoka-shard-3`.
+fn parse_ordinal(hostname: &str) -> Option<u64> {
+    let name = hostname.split('.').next()?;
+    let (_, ordinal) = name.rsplit_once('-')?;
+    ordinal.parse().ok()
+}
+
 #[derive(Debug)]
 pub struct Shard {
     bot: super::Bot,
+    cache: super::Cache,
     connect_amount: ShardConnectAmount,
+    events: super::Events,
+    gateway_intents: Intents,
+    gateway_queue_retry: super::QueueRetry,
+    gateway_queue_secret: Option<Sensitive<String>>,
+    gateway_queue_timeout: Duration,
     gateway_queue_url: Option<String>,
+    maintenance: super::Maintenance,
+    presence: super::Presence,
+    recorder: super::Recorder,
+    reshard: super::Reshard,
+    session_limit: super::SessionLimit,
+    sessions: super::Sessions,
 }
 
+const DEFAULT_GATEWAY_QUEUE_TIMEOUT_SECS: u64 = 5 * 60;
+
 const RECOMMENDED_SUGGESTION: &str = concat!(
     "Suggestion: If you want to use Discord's recommended amount of ",
     "shards to connect, please set `SHARD_USE_RECOMMENDED` to true"
@@ -110,6 +176,12 @@ enum InvalidShardConfig {
         "\"GATEWAY_QUEUE_URL\" must be in valid URL form or in HTTP/HTTPS"
     )]
     InvalidQueuerUrl,
+    #[error("Hostname does not end with a pod ordinal like `kyoka-shard-3`")]
+    NoHostnameOrdinal,
+    #[error(
+        "\"SHARD_USE_COORDINATOR\" requires \"GATEWAY_QUEUE_URL\" to be set"
+    )]
+    CoordinatorWithoutQueue,
 }
 
 impl Shard {
@@ -134,10 +206,41 @@ impl Shard {
             None
         };
 
+        let gateway_queue_secret = env::var("GATEWAY_QUEUE_SECRET")
+            .change_context(LoadError)?
+            .filter(|v| !v.is_empty())
+            .map(Sensitive::new);
+
+        let gateway_queue_timeout = env::var_parse("GATEWAY_QUEUE_TIMEOUT")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_GATEWAY_QUEUE_TIMEOUT_SECS);
+
+        let connect_amount = ShardConnectAmount::from_env()?;
+        if connect_amount == ShardConnectAmount::Coordinator
+            && queuer_url.is_none()
+        {
+            return Err(InvalidShardConfig::CoordinatorWithoutQueue)
+                .change_context(LoadError);
+        }
+
         Ok(Self {
             bot: super::Bot::from_env()?,
-            connect_amount: ShardConnectAmount::from_env()?,
+            cache: super::Cache::from_env()?,
+            connect_amount,
+            events: super::Events::from_env()?,
+            gateway_intents: super::intents::from_env()?,
+            gateway_queue_retry: super::QueueRetry::from_env()?,
+            gateway_queue_secret,
+            gateway_queue_timeout: Duration::from_secs(
+                gateway_queue_timeout.max(1),
+            ),
             gateway_queue_url: queuer_url,
+            maintenance: super::Maintenance::from_env()?,
+            presence: super::Presence::from_env()?,
+            recorder: super::Recorder::from_env()?,
+            reshard: super::Reshard::from_env()?,
+            session_limit: super::SessionLimit::from_env()?,
+            sessions: super::Sessions::from_env()?,
         })
     }
 }
@@ -148,13 +251,91 @@ impl Shard {
         &self.bot
     }
 
+    #[must_use]
+    pub const fn cache(&self) -> &super::Cache {
+        &self.cache
+    }
+
     #[must_use]
     pub const fn connect_amount(&self) -> &ShardConnectAmount {
         &self.connect_amount
     }
 
+    #[must_use]
+    pub const fn events(&self) -> &super::Events {
+        &self.events
+    }
+
+    /// Gateway intents to identify all shards with
+    #[must_use]
+    pub const fn gateway_intents(&self) -> Intents {
+        self.gateway_intents
+    }
+
+    #[must_use]
+    pub const fn gateway_queue_retry(&self) -> &super::QueueRetry {
+        &self.gateway_queue_retry
+    }
+
+    /// Shared secret to authenticate with the gateway queue server
+    #[must_use]
+    pub const fn gateway_queue_secret(&self) -> Option<&Sensitive<String>> {
+        self.gateway_queue_secret.as_ref()
+    }
+
+    /// How long to wait for the turn to identify from the gateway
+    /// queue server before asking again
+    #[must_use]
+    pub const fn gateway_queue_timeout(&self) -> Duration {
+        self.gateway_queue_timeout
+    }
+
     #[must_use]
     pub fn gateway_queue_url(&self) -> Option<&str> {
         self.gateway_queue_url.as_deref()
     }
+
+    #[must_use]
+    pub const fn maintenance(&self) -> &super::Maintenance {
+        &self.maintenance
+    }
+
+    #[must_use]
+    pub const fn presence(&self) -> &super::Presence {
+        &self.presence
+    }
+
+    #[must_use]
+    pub const fn recorder(&self) -> &super::Recorder {
+        &self.recorder
+    }
+
+    #[must_use]
+    pub const fn reshard(&self) -> &super::Reshard {
+        &self.reshard
+    }
+
+    #[must_use]
+    pub const fn session_limit(&self) -> &super::SessionLimit {
+        &self.session_limit
+    }
+
+    #[must_use]
+    pub const fn sessions(&self) -> &super::Sessions {
+        &self.sessions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_ordinal;
+
+    #[test]
+    fn test_parse_ordinal() {
+        assert_eq!(parse_ordinal("kyoka-shard-3"), Some(3));
+        assert_eq!(parse_ordinal("kyoka-shard-12.kyoka.default.svc"), Some(12));
+        assert_eq!(parse_ordinal("kyoka-shard"), None);
+        assert_eq!(parse_ordinal("kyoka"), None);
+        assert_eq!(parse_ordinal(""), None);
+    }
 }
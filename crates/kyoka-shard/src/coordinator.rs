@@ -0,0 +1,162 @@
+use error_stack::{Result, ResultExt};
+use kyoka::util::Sensitive;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::{App, SetupError};
+
+/// Shard range given by the coordinator to this process
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Assignment {
+    pub process: String,
+    pub id: u64,
+    pub amount: u64,
+    pub total: u64,
+    pub heartbeat_interval_secs: u64,
+}
+
+#[derive(Debug, Error)]
+#[error("Coordinator responded with an unexpected status: {0}")]
+struct UnexpectedStatus(StatusCode);
+
+#[derive(Debug, Serialize)]
+struct HeartbeatRequest<'a> {
+    process: &'a str,
+    name: &'a str,
+    id: u64,
+    amount: u64,
+    total: u64,
+}
+
+/// Keeps this process registered to the coordinator, which is
+/// part of the gateway queue server, for as long as it runs.
+#[derive(Debug, Clone)]
+pub struct CoordinatorClient {
+    assignment: Assignment,
+    client: reqwest::Client,
+    name: String,
+    queue_url: String,
+}
+
+impl CoordinatorClient {
+    /// Registers this process to the coordinator and
+    /// gets the range of shards it has to connect.
+    pub async fn register(
+        queue_url: &str,
+        secret: Option<&Sensitive<String>>,
+    ) -> Result<Self, SetupError> {
+        let client = crate::queue::make_client(secret);
+
+        let name = std::env::var("HOSTNAME")
+            .unwrap_or_else(|_| format!("pid-{}", std::process::id()));
+
+        let body = serde_json::json!({ "name": name });
+        let response = client
+            .post(format!("{queue_url}coordinator/register"))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .change_context(SetupError)?;
+
+        if response.status() != StatusCode::OK {
+            return Err(UnexpectedStatus(response.status()))
+                .change_context(SetupError)
+                .attach_printable("Failed to register to the coordinator");
+        }
+
+        let content = response.bytes().await.change_context(SetupError)?;
+        let assignment = serde_json::from_slice::<Assignment>(&content)
+            .change_context(SetupError)?;
+
+        tracing::info!(
+            process = %assignment.process,
+            id = %assignment.id,
+            amount = %assignment.amount,
+            total = %assignment.total,
+            "Registered to the coordinator"
+        );
+
+        Ok(Self { assignment, client, name, queue_url: queue_url.to_string() })
+    }
+
+    #[must_use]
+    pub const fn assignment(&self) -> &Assignment {
+        &self.assignment
+    }
+
+    async fn heartbeat(&self) -> reqwest::Result<StatusCode> {
+        let body = HeartbeatRequest {
+            process: &self.assignment.process,
+            name: &self.name,
+            id: self.assignment.id,
+            amount: self.assignment.amount,
+            total: self.assignment.total,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}coordinator/heartbeat", self.queue_url))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body).unwrap_or_default())
+            .send()
+            .await?;
+
+        Ok(response.status())
+    }
+
+    /// Sends heartbeats to the coordinator until the process
+    /// shuts down.
+    ///
+    /// The process shuts down if its shard range has been given
+    /// to another process, since both would connect the same shards.
+    pub async fn run_heartbeats(&self, app: App) {
+        let period =
+            Duration::from_secs(self.assignment.heartbeat_interval_secs);
+        let mut interval = tokio::time::interval(period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = app.shutdown_signal() => break,
+            }
+
+            match self.heartbeat().await {
+                Ok(StatusCode::CONFLICT) => {
+                    app.perform_shutdown(
+                        "Shard range has been reassigned by the coordinator",
+                    );
+                    break;
+                },
+                Ok(status) if !status.is_success() => {
+                    tracing::warn!(%status, "Coordinator rejected heartbeat");
+                },
+                Ok(..) => {},
+                Err(error) => {
+                    tracing::warn!(
+                        ?error,
+                        "Failed to send heartbeat to the coordinator"
+                    );
+                },
+            }
+        }
+    }
+
+    /// Releases the shard range so other processes can take it
+    /// immediately, without waiting for heartbeats to time out.
+    pub async fn unregister(&self) {
+        let body = serde_json::json!({ "process": self.assignment.process });
+        let result = self
+            .client
+            .post(format!("{}coordinator/unregister", self.queue_url))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await;
+
+        if let Err(error) = result {
+            tracing::warn!(?error, "Failed to unregister from the coordinator");
+        }
+    }
+}
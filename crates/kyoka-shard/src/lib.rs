@@ -2,8 +2,11 @@ mod app;
 
 pub mod bot;
 pub mod config;
+pub mod coordinator;
 pub mod metrics;
+pub mod presence;
 pub mod queue;
+pub mod shards;
 pub mod util;
 
 pub use app::App;
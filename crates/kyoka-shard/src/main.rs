@@ -1,6 +1,30 @@
 use error_stack::{Result, ResultExt};
+use kyoka_shard::config::{self, RuntimeFlavor};
 use kyoka_shard::{App, SetupError};
 
+/// Runs the metrics server in a dedicated thread with its own
+/// runtime, so scrapes and admin requests cannot starve gateway
+/// heartbeats of the shards.
+async fn metrics_server(app: App) -> Result<(), SetupError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::Builder::new()
+        .name("kyoka-metrics".into())
+        .spawn(move || {
+            let result = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .change_context(SetupError)
+                .and_then(|rt| {
+                    rt.block_on(kyoka_shard::metrics::server::start(app))
+                });
+
+            _ = tx.send(result);
+        })
+        .change_context(SetupError)?;
+
+    rx.await.change_context(SetupError)?
+}
+
 async fn runner() -> Result<(), SetupError> {
     let app = App::new()?;
 
@@ -9,7 +33,7 @@ async fn runner() -> Result<(), SetupError> {
     //
     // TODO: Implement graceful shutdown for these futures
     let service_result = tokio::try_join!(
-        kyoka_shard::metrics::server::start(app.clone()),
+        metrics_server(app.clone()),
         kyoka_shard::bot::start(app.clone()),
     );
     service_result?;
@@ -23,9 +47,24 @@ fn main() -> Result<(), SetupError> {
     // Any required events need to send by sentry will be
     // processed when `_sentry` variable is going to drop
     let _sentry = kyoka::sentry::init("kyoka-shard");
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(runner())
+
+    // Songbird mixes and decodes audio in its own threads, so
+    // only the shards and their events are running here.
+    let cfg = config::Runtime::from_env().change_context(SetupError)?;
+    tracing::debug!(cfg.runtime = ?cfg, "Building Tokio runtime");
+
+    let mut builder = match cfg.flavor() {
+        RuntimeFlavor::CurrentThread => {
+            tokio::runtime::Builder::new_current_thread()
+        },
+        RuntimeFlavor::MultiThread => {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            if let Some(threads) = cfg.worker_threads() {
+                builder.worker_threads(threads.get());
+            }
+            builder
+        },
+    };
+
+    builder.enable_all().build().change_context(SetupError)?.block_on(runner())
 }
@@ -0,0 +1,98 @@
+use actix_web::http::header;
+use actix_web::web::{self, ServiceConfig};
+use actix_web::{HttpRequest, HttpResponse};
+
+use super::AppContext;
+use crate::presence::PresenceSettings;
+
+/// Checks the bearer token of the request against the admin token.
+///
+/// It responds with `404 Not Found` if the admin API is disabled.
+fn authorize(req: &HttpRequest, ctx: &AppContext) -> Result<(), HttpResponse> {
+    let Some(expected) = ctx.admin_token.as_ref() else {
+        return Err(HttpResponse::NotFound().finish());
+    };
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match token {
+        Some(token) if expected.eq_constant_time(token) => Ok(()),
+        _ => Err(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .finish()),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn shards(req: HttpRequest, ctx: web::Data<AppContext>) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+    HttpResponse::Ok().json(ctx.app.shards().report(ctx.ready_tolerance))
+}
+
+#[tracing::instrument(skip(req, ctx))]
+async fn reconnect(
+    req: HttpRequest,
+    ctx: web::Data<AppContext>,
+    path: web::Path<u64>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    let id = path.into_inner();
+    match ctx.app.shards().reconnect(id) {
+        Some(Ok(())) => {
+            tracing::info!(shard.id = %id, "Reconnecting shard from admin API");
+            HttpResponse::Accepted().finish()
+        },
+        Some(Err(error)) => {
+            tracing::warn!(?error, shard.id = %id, "Failed to reconnect shard");
+            HttpResponse::Conflict().body("Shard is not connected")
+        },
+        None => HttpResponse::NotFound().body("Unknown shard"),
+    }
+}
+
+/// Replaces the presence of the bot, which is sent
+/// to all shards immediately and then rotated as usual.
+#[tracing::instrument(skip(req, ctx))]
+async fn presence(
+    req: HttpRequest,
+    ctx: web::Data<AppContext>,
+    body: web::Json<PresenceSettings>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+
+    ctx.app.presence().set(body.into_inner());
+    HttpResponse::Accepted().finish()
+}
+
+#[tracing::instrument(skip_all)]
+async fn shutdown(
+    req: HttpRequest,
+    ctx: web::Data<AppContext>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &ctx) {
+        return response;
+    }
+    ctx.app.perform_shutdown("Received shutdown request from admin API");
+    HttpResponse::Accepted().finish()
+}
+
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .route("/shards", web::get().to(shards))
+            .route("/shards/{id}/reconnect", web::post().to(reconnect))
+            .route("/presence", web::post().to(presence))
+            .route("/shutdown", web::post().to(shutdown)),
+    );
+}
@@ -1,26 +1,93 @@
+mod admin;
 mod router;
 pub mod server;
 
 use actix_web_prom::PrometheusMetrics;
 use error_stack::{Result, ResultExt};
 use kyoka::metrics::MetricsSetupError;
-use prometheus::{Gauge, IntGauge};
+use kyoka::util::Sensitive;
+use prometheus::{HistogramVec, IntCounterVec, IntGaugeVec};
 use prometheus_macros::composite_metric;
+use std::time::Duration;
+
+use crate::{shards::ShardStatus, App};
+
+#[derive(Debug)]
+pub struct AppContext {
+    pub admin_token: Option<Sensitive<String>>,
+    pub app: App,
+    pub ready_tolerance: Duration,
+}
 
 composite_metric! {
     #[derive(Debug, Clone)]
     pub struct Metrics {
         #[name = "events_processed"]
-        #[desc = "All events processed in all shards"]
-        events_processed: IntGauge,
+        #[desc = "Events received from each shard by event kind"]
+        #[labels = ["shard", "event"]]
+        events_processed: IntCounterVec,
         #[name = "shard_latency"]
-        #[desc = "Latency of each shards"]
+        #[desc = "Heartbeat latency of each shard in seconds"]
+        #[labels = ["shard"]]
+        shard_latency: HistogramVec,
+        #[name = "shard_status"]
+        #[desc = "Connection status of each shard"]
+        #[labels = ["shard", "status"]]
+        shard_status: IntGaugeVec,
+        #[name = "shard_reconnects"]
+        #[desc = "Reconnections made by each shard"]
+        #[labels = ["shard"]]
+        shard_reconnects: IntCounterVec,
+        #[name = "shard_resumes"]
+        #[desc = "Sessions resumed by each shard"]
+        #[labels = ["shard"]]
+        shard_resumes: IntCounterVec,
+        #[name = "shard_restarts"]
+        #[desc = "Restarts of each shard after fatally closed"]
+        #[labels = ["shard"]]
+        shard_restarts: IntCounterVec,
+        #[name = "event_queue_depth"]
+        #[desc = "Events waiting to be processed in each shard"]
         #[labels = ["shard"]]
-        shard_latency: Gauge,
+        event_queue_depth: IntGaugeVec,
+        #[name = "events_dropped"]
+        #[desc = "Low priority events dropped due to a full queue"]
+        #[labels = ["shard"]]
+        events_dropped: IntCounterVec,
+        #[name = "event_handle_duration"]
+        #[desc = "Time taken to process each event in seconds"]
+        #[labels = ["event"]]
+        event_handle_duration: HistogramVec,
+        #[name = "shard_count"]
+        #[desc = "Current and Discord's recommended amount of shards"]
+        #[labels = ["kind"]]
+        shard_count: IntGaugeVec,
+        #[name = "reshard_decisions"]
+        #[desc = "Decisions made whenever the recommended amount of shards is checked"]
+        #[labels = ["decision"]]
+        reshard_decisions: IntCounterVec,
+        #[name = "gateway_queue_mode"]
+        #[desc = "Whether shards identify through the remote or local queue"]
+        #[labels = ["mode"]]
+        gateway_queue_mode: IntGaugeVec,
+        #[name = "gateway_queue_retries"]
+        #[desc = "Retried requests to the gateway queue server"]
+        #[labels = ["shard"]]
+        gateway_queue_retries: IntCounterVec,
     }
 }
 
 impl Metrics {
+    /// Marks the current connection status of a shard, clearing
+    /// the rest of the statuses for the shard.
+    pub fn set_shard_status(&self, shard: &str, status: ShardStatus) {
+        for other in ShardStatus::ALL {
+            self.shard_status
+                .with_label_values(&[shard, other.as_str()])
+                .set(i64::from(other == status));
+        }
+    }
+
     pub fn setup(
         &self,
         metrics: &PrometheusMetrics,
@@ -35,6 +102,61 @@ impl Metrics {
             .register(Box::new(self.events_processed.clone()))
             .change_context(MetricsSetupError)?;
 
+        metrics
+            .registry
+            .register(Box::new(self.shard_status.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.shard_reconnects.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.shard_resumes.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.shard_restarts.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.event_queue_depth.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.events_dropped.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.event_handle_duration.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.shard_count.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.reshard_decisions.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.gateway_queue_mode.clone()))
+            .change_context(MetricsSetupError)?;
+
+        metrics
+            .registry
+            .register(Box::new(self.gateway_queue_retries.clone()))
+            .change_context(MetricsSetupError)?;
+
         Ok(())
     }
 }
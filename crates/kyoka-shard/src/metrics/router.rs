@@ -1,11 +1,37 @@
 use actix_web::web::{self, ServiceConfig};
 use actix_web::HttpResponse;
 
+use super::AppContext;
+
 #[tracing::instrument]
-pub async fn health() -> HttpResponse {
+pub async fn live() -> HttpResponse {
     HttpResponse::Ok().body("I'm healthy and alive!")
 }
 
+#[tracing::instrument(skip_all)]
+pub async fn ready(ctx: web::Data<AppContext>) -> HttpResponse {
+    let report = ctx.app.shards().report(ctx.ready_tolerance);
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+/// Session start limit as last seen by this process, which is
+/// unknown until the bot starts.
+#[tracing::instrument(skip_all)]
+pub async fn session_limit(ctx: web::Data<AppContext>) -> HttpResponse {
+    match ctx.app.session_limiter().report() {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::ServiceUnavailable()
+            .body("Session start limit is not known yet"),
+    }
+}
+
 pub fn configure(cfg: &mut ServiceConfig) {
-    cfg.route("/health", web::get().to(health));
+    cfg.route("/health", web::get().to(live))
+        .route("/health/live", web::get().to(live))
+        .route("/health/ready", web::get().to(ready))
+        .route("/session-limit", web::get().to(session_limit));
 }
@@ -3,6 +3,7 @@ use std::time::Duration;
 use actix_web_prom::PrometheusMetricsBuilder;
 use error_stack::{Result, ResultExt};
 use kyoka::metrics::SystemMetrics;
+use kyoka::util::Sensitive;
 use tokio::task::JoinSet;
 
 use crate::{config, App, SetupError};
@@ -30,6 +31,9 @@ pub async fn start(app: App) -> Result<(), SetupError> {
         cfg.host(),
         cfg.port()
     );
+    if cfg.admin_token().is_some() {
+        tracing::info!("Admin API is enabled at /admin");
+    }
 
     let system_metrics = system_metrics.clone();
     let job_app = app.clone();
@@ -55,9 +59,17 @@ pub async fn start(app: App) -> Result<(), SetupError> {
         tracing::debug!("Ending system usage metrics measure job");
     });
 
+    let context = actix_web::web::Data::new(super::AppContext {
+        admin_token: cfg.admin_token().map(|v| Sensitive::new(v.to_string())),
+        app: app.clone(),
+        ready_tolerance: cfg.ready_tolerance(),
+    });
+
     let server = actix_web::HttpServer::new(move || {
         actix_web::App::new()
+            .app_data(context.clone())
             .configure(super::router::configure)
+            .configure(super::admin::configure)
             .wrap(prometheus.clone())
             .wrap(sentry_actix::Sentry::new())
     })
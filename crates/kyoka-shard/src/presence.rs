@@ -0,0 +1,148 @@
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+use twilight_model::gateway::presence::{
+    Activity, ActivityType, MinimalActivity, Status,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    Playing,
+    Streaming,
+    Listening,
+    Watching,
+    Competing,
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "Unknown activity kind, expected `playing`, `streaming`, `listening`, `watching` or `competing`"
+)]
+pub struct UnknownActivityKind;
+
+impl FromStr for ActivityKind {
+    type Err = UnknownActivityKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "playing" => Ok(Self::Playing),
+            "streaming" => Ok(Self::Streaming),
+            "listening" => Ok(Self::Listening),
+            "watching" => Ok(Self::Watching),
+            "competing" => Ok(Self::Competing),
+            _ => Err(UnknownActivityKind),
+        }
+    }
+}
+
+impl From<ActivityKind> for ActivityType {
+    fn from(kind: ActivityKind) -> Self {
+        match kind {
+            ActivityKind::Playing => Self::Playing,
+            ActivityKind::Streaming => Self::Streaming,
+            ActivityKind::Listening => Self::Listening,
+            ActivityKind::Watching => Self::Watching,
+            ActivityKind::Competing => Self::Competing,
+        }
+    }
+}
+
+/// Values to fill in the placeholders of activity text
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PresenceStats {
+    pub guild_count: usize,
+    pub playing_count: usize,
+    pub shard_count: u64,
+}
+
+/// Activity with text that may contain placeholders like
+/// `{guild_count}`, `{playing_count}` and `{shard_count}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ActivityTemplate {
+    pub kind: ActivityKind,
+    pub text: String,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl ActivityTemplate {
+    #[must_use]
+    pub fn render(&self, stats: &PresenceStats) -> Activity {
+        let name = self
+            .text
+            .replace("{guild_count}", &stats.guild_count.to_string())
+            .replace("{playing_count}", &stats.playing_count.to_string())
+            .replace("{shard_count}", &stats.shard_count.to_string());
+
+        MinimalActivity { kind: self.kind.into(), name, url: self.url.clone() }
+            .into()
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid activity, expected `<kind>:<text>`")]
+pub struct InvalidActivity;
+
+impl FromStr for ActivityTemplate {
+    type Err = InvalidActivity;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, text) = s.split_once(':').ok_or(InvalidActivity)?;
+        let kind = kind.parse().map_err(|_| InvalidActivity)?;
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(InvalidActivity);
+        }
+
+        Ok(Self { kind, text: text.to_string(), url: None })
+    }
+}
+
+/// Presence of the bot, with activities to rotate through
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PresenceSettings {
+    pub status: Status,
+    pub activities: Vec<ActivityTemplate>,
+}
+
+impl Default for PresenceSettings {
+    fn default() -> Self {
+        Self { status: Status::Online, activities: Vec::new() }
+    }
+}
+
+/// Presence shared between the bot and the admin API, so
+/// it can be changed while the bot is running.
+#[derive(Debug, Clone, Default)]
+pub struct Presence {
+    settings: Arc<RwLock<PresenceSettings>>,
+    changed: Arc<Notify>,
+}
+
+impl Presence {
+    #[must_use]
+    pub fn get(&self) -> PresenceSettings {
+        self.settings.read().expect("presence lock poisoned").clone()
+    }
+
+    /// Sets the presence to start the bot with, which
+    /// is sent to shards upon identifying.
+    pub fn init(&self, settings: PresenceSettings) {
+        *self.settings.write().expect("presence lock poisoned") = settings;
+    }
+
+    /// Replaces the presence and lets the bot send it to all shards.
+    pub fn set(&self, settings: PresenceSettings) {
+        self.init(settings);
+        self.changed.notify_one();
+    }
+
+    /// Waits until the presence is replaced with [`Presence::set`]
+    pub fn changed(&self) -> Notified<'_> {
+        self.changed.notified()
+    }
+}
@@ -1,73 +1,736 @@
-use error_stack::{Result, ResultExt};
+use error_stack::{Report, Result, ResultExt};
+use kyoka::util::{
+    Handshake, Sensitive, SessionLimiter, GATEWAY_QUEUE_PROTOCOL,
+    GATEWAY_QUEUE_TICKET_HEADER,
+};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use std::{fmt::Debug, sync::Arc};
-use tokio::sync::Mutex;
+use thiserror::Error;
+use tokio::time::Instant;
 use twilight_gateway_queue::{LocalQueue, Queue};
+use twilight_model::id::{marker::ApplicationMarker, Id};
 
-use crate::SetupError;
+use crate::config::QueueRetry;
+use crate::metrics::Metrics;
+use crate::{App, SetupError};
 
-#[derive(Debug, Clone)]
+/// Extra time given to the gateway queue server to respond
+/// after the maximum wait time has passed
+const RESPONSE_GRACE: Duration = Duration::from_secs(10);
+
+/// Maximum time to wait for the gateway queue server to
+/// respond to a handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("Gateway queue server could not be reached")]
+    Unreachable,
+    #[error("Gateway queue server rejected the handshake: {0}")]
+    Rejected(StatusCode),
+    #[error(
+        "Gateway queue server speaks protocol version {0}, expected {GATEWAY_QUEUE_PROTOCOL}"
+    )]
+    Protocol(u32),
+}
+
+impl HandshakeError {
+    /// Whether the handshake may succeed if it's tried again later
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Unreachable => true,
+            Self::Rejected(status) => status.is_server_error(),
+            Self::Protocol(..) => false,
+        }
+    }
+}
+
+/// Whether the request may succeed if it's tried again later.
+///
+/// Errors other than transport errors and server errors mean that
+/// the server refuses this bot, which retrying cannot fix.
+fn is_retryable(error: &reqwest::Error) -> bool {
+    error.status().map_or(true, |status| status.is_server_error())
+}
+
+/// Delay before retrying a failed request, spread out so shards
+/// of every process do not retry at the same time.
+///
+/// It is somewhere between the half and the whole of the
+/// exponential backoff for the attempt.
+fn backoff(retry: &QueueRetry, attempt: u32) -> Duration {
+    let delay = retry
+        .base_delay()
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(retry.max_delay());
+
+    let half = delay / 2;
+    half + rand::thread_rng().gen_range(Duration::ZERO..=delay - half)
+}
+
+#[derive(Debug, Error)]
+enum RequestError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("Gave up waiting for the turn from the gateway queue")]
+    GaveUp,
+}
+
+impl RequestError {
+    /// Whether the server refuses this bot, in which case shards
+    /// must not identify without it either.
+    fn is_rejected(&self) -> bool {
+        matches!(self, Self::Http(error) if !is_retryable(error))
+    }
+}
+
+/// Identifies through the gateway queue server, retrying failed
+/// requests with exponential backoff.
+///
+/// Once the retries are used up, shards identify through a local
+/// queue until the server is healthy again. Requests rejected by
+/// the server are not retried and shut down the process instead.
+#[derive(Debug)]
 pub struct BotQueue {
+    application_id: Id<ApplicationMarker>,
     client: reqwest::Client,
     queue_url: String,
-    // Fallback queue if things fall apart
-    local: Arc<Mutex<Option<LocalQueue>>>,
+    retry: QueueRetry,
+    timeout: Duration,
+    local: LocalQueue,
+    local_mode: Arc<AtomicBool>,
+    app: App,
+}
+
+/// Creates a client for the gateway queue server which sends
+/// the shared secret, if there's any, with every request.
+pub(crate) fn make_client(
+    secret: Option<&Sensitive<String>>,
+) -> reqwest::Client {
+    let mut headers = HeaderMap::new();
+    if let Some(secret) = secret {
+        let mut value =
+            HeaderValue::from_str(&format!("Bearer {}", secret.as_str()))
+                .expect("gateway queue secret should be a valid header value");
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .default_headers(headers)
+        .build()
+        .expect("Failed to configure reqwest client")
+}
+
+/// Checks whether the gateway queue server is reachable, speaks
+/// the same protocol and accepts this bot, without taking any
+/// identify slot from it.
+async fn handshake(
+    client: &reqwest::Client,
+    queue_url: &str,
+    application_id: Id<ApplicationMarker>,
+) -> Result<Handshake, HandshakeError> {
+    let response = client
+        .get(format!("{queue_url}queue/handshake"))
+        .query(&[
+            ("application", application_id.get()),
+            ("protocol", u64::from(GATEWAY_QUEUE_PROTOCOL)),
+        ])
+        .timeout(HANDSHAKE_TIMEOUT)
+        .send()
+        .await
+        .change_context(HandshakeError::Unreachable)?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(Report::new(HandshakeError::Rejected(status)))
+            .attach_printable(body);
+    }
+
+    let content =
+        response.bytes().await.change_context(HandshakeError::Unreachable)?;
+    let handshake = serde_json::from_slice::<Handshake>(&content)
+        .change_context(HandshakeError::Rejected(status))
+        .attach_printable("Invalid handshake response")?;
+
+    if handshake.protocol != GATEWAY_QUEUE_PROTOCOL {
+        return Err(Report::new(HandshakeError::Protocol(handshake.protocol)));
+    }
+
+    Ok(handshake)
+}
+
+fn set_mode(metrics: &Metrics, local: bool) {
+    let mode = metrics.gateway_queue_mode();
+    mode.with_label_values(&["remote"]).set(i64::from(!local));
+    mode.with_label_values(&["local"]).set(i64::from(local));
 }
 
 impl BotQueue {
-    pub async fn new(queue_url: &str) -> Result<Self, SetupError> {
-        // Test the service first before we actually connect
-        // all shards in a single process otherwise we're wasting
-        // the identify cap from Discord
+    /// Connects to the gateway queue server, which may serve
+    /// other bots as well as the application of this bot.
+    pub async fn new(
+        queue_url: &str,
+        secret: Option<&Sensitive<String>>,
+        application_id: Id<ApplicationMarker>,
+        timeout: Duration,
+        retry: QueueRetry,
+        app: App,
+    ) -> Result<Self, SetupError> {
         let queue = Self {
-            client: reqwest::Client::builder()
-                .use_rustls_tls()
-                .build()
-                .expect("Failed to configure reqwest client"),
+            application_id,
+            client: make_client(secret),
             queue_url: queue_url.to_string(),
-            local: Arc::new(Mutex::new(None)),
+            retry,
+            timeout,
+            local: LocalQueue::new(),
+            local_mode: Arc::new(AtomicBool::new(false)),
+            app,
         };
 
-        queue.request_for_shard(0).await.change_context(SetupError)?;
+        let mut attempt = 0;
+        let handshake = loop {
+            let error = match queue.handshake().await {
+                Ok(handshake) => break handshake,
+                Err(error) => error,
+            };
+
+            let retryable = error.current_context().is_retryable();
+            if !retryable || attempt >= queue.retry.attempts() {
+                return Err(error).change_context(SetupError);
+            }
+
+            let delay = backoff(&queue.retry, attempt);
+            tracing::warn!(
+                ?error,
+                ?delay,
+                "Failed to handshake with the gateway queue, retrying"
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        tracing::info!(
+            protocol = %handshake.protocol,
+            max_concurrency = %handshake.max_concurrency,
+            "Connected to the gateway queue"
+        );
+        set_mode(queue.app.metrics(), false);
         Ok(queue)
     }
 
-    async fn request_for_shard(&self, id: u64) -> reqwest::Result<()> {
-        let local = self.local.lock().await;
-        if let Some(local) = local.as_ref() {
-            local.request([id, 1]).await;
-        } else {
-            self.client
-                .post(format!("{}queue?shard={id}", self.queue_url))
-                .send()
-                .await?;
+    async fn handshake(&self) -> Result<Handshake, HandshakeError> {
+        handshake(&self.client, &self.queue_url, self.application_id).await
+    }
+
+    /// Requests for the turn of the shard from the server, retrying
+    /// up to the configured amount of attempts.
+    ///
+    /// It gives up once the shard has waited longer than allowed,
+    /// counting every retry and every wait asked by the server.
+    async fn request_remote(
+        &self,
+        id: u64,
+        total: u64,
+    ) -> std::result::Result<(), RequestError> {
+        let deadline = Instant::now() + self.retry.give_up_after();
+        let mut attempt = 0;
+        loop {
+            let result = self.request_for_shard(id, total, deadline).await;
+            let Err(error) = result else {
+                return Ok(());
+            };
+
+            let RequestError::Http(http) = &error else {
+                return Err(error);
+            };
+            if !is_retryable(http) || attempt >= self.retry.attempts() {
+                return Err(error);
+            }
+
+            let delay = backoff(&self.retry, attempt);
+            if Instant::now() + delay > deadline {
+                return Err(RequestError::GaveUp);
+            }
+
+            tracing::warn!(
+                ?error,
+                ?delay,
+                shard.id = %id,
+                "Failed to request from the gateway queue, retrying"
+            );
+            self.app
+                .metrics()
+                .gateway_queue_retries()
+                .with_label_values(&[&id.to_string()])
+                .inc();
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
+    }
+
+    async fn request_for_shard(
+        &self,
+        id: u64,
+        total: u64,
+        deadline: Instant,
+    ) -> std::result::Result<(), RequestError> {
+        // Keeps the place in the queue across timed out requests
+        let mut ticket = None;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(RequestError::GaveUp);
+            }
 
-        Ok(())
+            let mut request = self
+                .client
+                .post(format!("{}queue", self.queue_url))
+                .query(&[
+                    ("application", self.application_id.get()),
+                    ("shard", id),
+                    ("total", total),
+                    ("max_wait", self.timeout.as_secs()),
+                ])
+                // The server should give up waiting first
+                .timeout(self.timeout + RESPONSE_GRACE);
+
+            if let Some(ticket) = ticket {
+                request = request.query(&[("ticket", ticket)]);
+            }
+
+            let response = request.send().await?;
+            if response.status() == StatusCode::REQUEST_TIMEOUT {
+                tracing::warn!(
+                    shard.id = %id,
+                    "Still waiting for the turn to identify, asking again"
+                );
+                ticket = response
+                    .headers()
+                    .get(GATEWAY_QUEUE_TICKET_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok());
+                continue;
+            }
+
+            // The server holds off identifies if the session
+            // start limit is almost exhausted
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+
+            let status = response.status();
+            match retry_after {
+                Some(secs) if status == StatusCode::SERVICE_UNAVAILABLE => {
+                    let delay = Duration::from_secs(secs);
+                    if Instant::now() + delay > deadline {
+                        return Err(RequestError::GaveUp);
+                    }
+
+                    tracing::warn!(
+                        shard.id = %id,
+                        "Gateway queue asked to retry in {secs} second/s"
+                    );
+                    tokio::time::sleep(delay).await;
+                },
+                _ => {
+                    response.error_for_status()?;
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    /// Switches to the local queue and checks the server on an
+    /// interval until it is healthy again.
+    fn fall_back(&self) {
+        if self.local_mode.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        tracing::warn!(
+            "Falling back to local queue until the gateway queue is healthy"
+        );
+        set_mode(self.app.metrics(), true);
+
+        let client = self.client.clone();
+        let queue_url = self.queue_url.clone();
+        let application_id = self.application_id;
+        let local_mode = self.local_mode.clone();
+        let metrics = self.app.metrics().clone();
+        let period = self.retry.probe_interval();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(period).await;
+
+                match handshake(&client, &queue_url, application_id).await {
+                    Ok(..) => break,
+                    Err(error) => {
+                        tracing::debug!(
+                            ?error,
+                            "Gateway queue is still unhealthy"
+                        );
+                    },
+                }
+            }
+
+            tracing::info!(
+                "Gateway queue is healthy again, switching back to it"
+            );
+            local_mode.store(false, Ordering::SeqCst);
+            set_mode(&metrics, false);
+        });
     }
 }
 
 impl Queue for BotQueue {
     fn request<'a>(
         &'a self,
-        [id, ..]: [u64; 2],
+        [id, total]: [u64; 2],
     ) -> std::pin::Pin<Box<dyn futures::Future<Output = ()> + Send + 'a>> {
         Box::pin(async move {
             tracing::info!("waiting for allowance on shard {id}");
-            if let Err(error) = self.request_for_shard(id).await {
+            if !self.local_mode.load(Ordering::SeqCst) {
+                let Err(error) = self.request_remote(id, total).await else {
+                    return;
+                };
+
+                if error.is_rejected() {
+                    // Identifying without the server would go around
+                    // the queue other processes are waiting in, so
+                    // the shard is left to be closed by the shutdown.
+                    tracing::error!(
+                        ?error,
+                        shard.id = %id,
+                        "Gateway queue server rejected the request for shard"
+                    );
+                    self.app.perform_shutdown(
+                        "Gateway queue server rejected the request for shard",
+                    );
+                    return;
+                }
+
                 tracing::error!(
                     ?error,
+                    shard.id = %id,
                     "Failed to request for shard from a server queue"
                 );
-                tracing::info!("Falling back to local queue");
+                self.fall_back();
+            }
+
+            self.local.request([id, total]).await;
+        })
+    }
+}
+
+/// Holds off identifies once the session start limit of the bot
+/// reaches its floor, until the limit resets.
+#[derive(Debug)]
+pub struct LimitedQueue {
+    floor: u64,
+    inner: Arc<dyn Queue>,
+    limiter: SessionLimiter,
+}
+
+impl LimitedQueue {
+    #[must_use]
+    pub fn new(
+        inner: Arc<dyn Queue>,
+        limiter: SessionLimiter,
+        floor: u64,
+    ) -> Self {
+        Self { floor, inner, limiter }
+    }
+}
+
+/// Session start taken for an identify, which is given back if
+/// the request is cancelled before the shard gets its turn.
+struct SessionStart<'a> {
+    limiter: &'a SessionLimiter,
+    used: bool,
+}
+
+impl Drop for SessionStart<'_> {
+    fn drop(&mut self) {
+        if !self.used {
+            self.limiter.release();
+        }
+    }
+}
+
+impl Queue for LimitedQueue {
+    fn request<'a>(
+        &'a self,
+        shard: [u64; 2],
+    ) -> std::pin::Pin<Box<dyn futures::Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            while let Err(wait) = self.limiter.acquire(self.floor) {
+                tracing::warn!(
+                    shard.id = %shard[0],
+                    ?wait,
+                    floor = %self.floor,
+                    "Session start limit is almost exhausted, waiting until it resets"
+                );
+                tokio::time::sleep(wait.max(Duration::from_secs(1))).await;
+            }
+
+            let mut start =
+                SessionStart { limiter: &self.limiter, used: false };
+            self.inner.request(shard).await;
+            start.used = true;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff, is_retryable, BotQueue, LimitedQueue, QueueRetry};
+    use crate::App;
+    use kyoka::util::{Handshake, SessionLimiter, GATEWAY_QUEUE_PROTOCOL};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use twilight_gateway_queue::{LocalQueue, Queue};
+    use twilight_model::gateway::SessionStartLimit;
+    use twilight_model::id::Id;
+
+    fn handshake() -> String {
+        let handshake = Handshake {
+            protocol: GATEWAY_QUEUE_PROTOCOL,
+            application: None,
+            max_concurrency: 1,
+        };
+        serde_json::to_string(&handshake).unwrap()
+    }
 
-                let mut local = self.local.lock().await;
-                *local = Some(LocalQueue::new());
-                drop(local);
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n{headers}\r\n{body}",
+            body.len()
+        )
+    }
 
-                self.request_for_shard(id)
-                    .await
-                    .expect("Should not crash with local queue");
+    /// Serves every request with the response given by `respond`
+    /// from its request line, returning the URL of the server.
+    async fn serve<F>(respond: F) -> String
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut stream, ..)) = listener.accept().await {
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut buffer = vec![0; 4096];
+                    let read = stream.read(&mut buffer).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buffer[..read]);
+                    let line = request.lines().next().unwrap_or_default();
+                    let _ = stream.write_all(respond(line).as_bytes()).await;
+                });
+            }
+        });
+        url
+    }
+
+    fn retry(attempts: u32, give_up_after: Duration) -> QueueRetry {
+        QueueRetry::new(
+            attempts,
+            Duration::from_millis(10),
+            Duration::from_millis(20),
+            give_up_after,
+            Duration::from_millis(50),
+        )
+    }
+
+    fn bot_queue(queue_url: String, retry: QueueRetry) -> BotQueue {
+        BotQueue {
+            application_id: Id::new(1),
+            client: reqwest::Client::new(),
+            queue_url,
+            retry,
+            timeout: Duration::from_secs(1),
+            local: LocalQueue::new(),
+            local_mode: Arc::default(),
+            app: App::for_test(),
+        }
+    }
+
+    async fn request_error(url: String) -> reqwest::Error {
+        let result = reqwest::get(url).await.and_then(|r| r.error_for_status());
+        result.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn test_is_retryable() {
+        let url = serve(|line| {
+            if line.contains("/unavailable") {
+                response("503 Service Unavailable", "", "")
+            } else {
+                response("401 Unauthorized", "", "")
             }
         })
+        .await;
+
+        assert!(is_retryable(
+            &request_error(format!("{url}unavailable")).await
+        ));
+        assert!(!is_retryable(&request_error(format!("{url}queue")).await));
+
+        // Nothing listens on the port anymore
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(is_retryable(&request_error(format!("http://{addr}/")).await));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry = QueueRetry::new(
+            3,
+            Duration::from_secs(1),
+            Duration::from_secs(30),
+            Duration::from_secs(60),
+            Duration::from_secs(30),
+        );
+
+        for attempt in [0, 1, 3, 4, 5, 10, u32::MAX] {
+            let delay = Duration::from_secs(2u64.saturating_pow(attempt))
+                .min(Duration::from_secs(30));
+
+            let backoff = backoff(&retry, attempt);
+            assert!(backoff >= delay / 2, "{backoff:?} for {attempt}");
+            assert!(backoff <= delay, "{backoff:?} for {attempt}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_and_recovers() {
+        let healthy = Arc::new(AtomicBool::new(false));
+        let url = serve({
+            let healthy = healthy.clone();
+            move |line| {
+                if !healthy.load(Ordering::SeqCst) {
+                    response("500 Internal Server Error", "", "")
+                } else if line.contains("/queue/handshake") {
+                    response("200 OK", "", &handshake())
+                } else {
+                    response("204 No Content", "", "")
+                }
+            }
+        })
+        .await;
+
+        let queue = bot_queue(url, retry(1, Duration::from_secs(60)));
+        tokio::time::timeout(Duration::from_secs(5), queue.request([0, 1]))
+            .await
+            .expect("shard should identify through the local queue");
+        assert!(queue.local_mode.load(Ordering::SeqCst));
+
+        // The probe keeps failing while the server is unhealthy
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(queue.local_mode.load(Ordering::SeqCst));
+
+        healthy.store(true, Ordering::SeqCst);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while queue.local_mode.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("probe should switch back to the gateway queue");
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_on_long_waits() {
+        let url = serve(|_| {
+            response("503 Service Unavailable", "retry-after: 3600\r\n", "")
+        })
+        .await;
+
+        let queue = bot_queue(url, retry(0, Duration::from_secs(60)));
+        tokio::time::timeout(Duration::from_secs(5), queue.request([0, 1]))
+            .await
+            .expect("shard should not wait an hour for its turn");
+        assert!(queue.local_mode.load(Ordering::SeqCst));
+        assert!(!queue.app.has_shutdown());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_request_shuts_down() {
+        let url = serve(|_| response("401 Unauthorized", "", "")).await;
+
+        let queue = bot_queue(url, retry(3, Duration::from_secs(60)));
+        tokio::time::timeout(Duration::from_secs(5), queue.request([0, 1]))
+            .await
+            .expect("rejected request should not hang");
+        assert!(queue.app.has_shutdown());
+        assert!(!queue.local_mode.load(Ordering::SeqCst));
+    }
+
+    /// Gateway queue which never gives the turn to any shard
+    #[derive(Debug)]
+    struct StuckQueue;
+
+    impl Queue for StuckQueue {
+        fn request<'a>(
+            &'a self,
+            _: [u64; 2],
+        ) -> std::pin::Pin<Box<dyn futures::Future<Output = ()> + Send + 'a>>
+        {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    fn limiter(remaining: u64) -> SessionLimiter {
+        SessionLimiter::new(&SessionStartLimit {
+            max_concurrency: 1,
+            remaining,
+            reset_after: 60_000,
+            total: 1000,
+        })
+    }
+
+    fn remaining(limiter: &SessionLimiter) -> u64 {
+        limiter.report().unwrap().remaining
+    }
+
+    #[tokio::test]
+    async fn test_limited_queue_takes_session_start() {
+        let limiter = limiter(3);
+        let queue =
+            LimitedQueue::new(Arc::new(LocalQueue::new()), limiter.clone(), 1);
+
+        queue.request([0, 1]).await;
+        assert_eq!(remaining(&limiter), 2);
+    }
+
+    #[tokio::test]
+    async fn test_limited_queue_cancelled_request() {
+        let limiter = limiter(3);
+        let queue = LimitedQueue::new(Arc::new(StuckQueue), limiter.clone(), 1);
+
+        let mut request = queue.request([0, 1]);
+        let result =
+            tokio::time::timeout(Duration::from_millis(50), &mut request).await;
+        assert!(result.is_err());
+
+        // The session start is taken while waiting for the turn
+        assert_eq!(remaining(&limiter), 2);
+
+        drop(request);
+        assert_eq!(remaining(&limiter), 3);
     }
 }
@@ -0,0 +1,219 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use twilight_gateway::error::ChannelError;
+use twilight_gateway::{CloseFrame, ConnectionStatus, MessageSender};
+use twilight_model::gateway::payload::outgoing::UpdatePresence;
+use twilight_model::id::marker::GuildMarker;
+use twilight_model::id::Id;
+
+/// Connection status of a shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardStatus {
+    Disconnected,
+    Identifying,
+    Resuming,
+    Active,
+}
+
+impl ShardStatus {
+    pub const ALL: [Self; 4] =
+        [Self::Disconnected, Self::Identifying, Self::Resuming, Self::Active];
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Disconnected => "disconnected",
+            Self::Identifying => "identifying",
+            Self::Resuming => "resuming",
+            Self::Active => "active",
+        }
+    }
+}
+
+impl From<&ConnectionStatus> for ShardStatus {
+    fn from(status: &ConnectionStatus) -> Self {
+        match status {
+            ConnectionStatus::Connected => Self::Active,
+            ConnectionStatus::Identifying => Self::Identifying,
+            ConnectionStatus::Resuming => Self::Resuming,
+            _ => Self::Disconnected,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    status: ShardStatus,
+    /// Whether `Ready` or `Resumed` is received in the current session
+    ready: bool,
+    active_since: Option<Instant>,
+    /// When the shard stopped being healthy after it was healthy before
+    unhealthy_since: Option<Instant>,
+    latency: Option<Duration>,
+    sender: Option<MessageSender>,
+}
+
+impl Entry {
+    const fn is_healthy(&self) -> bool {
+        matches!(self.status, ShardStatus::Active) && self.ready
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShardReport {
+    pub id: u64,
+    pub status: ShardStatus,
+    pub ready: bool,
+    pub uptime_secs: Option<u64>,
+    pub latency_secs: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub ready: bool,
+    pub shards: Vec<ShardReport>,
+}
+
+/// Keeps track of the status of all shards running in this process.
+#[derive(Debug, Clone, Default)]
+pub struct Shards {
+    entries: Arc<RwLock<BTreeMap<u64, Entry>>>,
+    /// Total number of shards of the bot, including shards
+    /// running in other processes
+    total: Arc<AtomicU64>,
+}
+
+impl Shards {
+    fn update(&self, id: u64, f: impl FnOnce(&mut Entry)) {
+        let mut entries = self.entries.write().expect("shards lock poisoned");
+        let entry = entries.entry(id).or_insert(Entry {
+            status: ShardStatus::Disconnected,
+            ready: false,
+            active_since: None,
+            unhealthy_since: None,
+            latency: None,
+            sender: None,
+        });
+
+        let was_healthy = entry.is_healthy();
+        f(entry);
+
+        match (was_healthy, entry.is_healthy()) {
+            (true, false) => entry.unhealthy_since = Some(Instant::now()),
+            (false, true) => entry.unhealthy_since = None,
+            _ => {},
+        }
+    }
+
+    pub fn set_status(&self, id: u64, status: ShardStatus) {
+        self.update(id, |entry| {
+            if entry.status == status {
+                return;
+            }
+
+            entry.status = status;
+            if status == ShardStatus::Active {
+                entry.active_since = Some(Instant::now());
+            } else {
+                entry.active_since = None;
+                entry.ready = false;
+            }
+        });
+    }
+
+    /// Marks the shard that it received `Ready` or `Resumed` event
+    pub fn set_ready(&self, id: u64) {
+        self.update(id, |entry| entry.ready = true);
+    }
+
+    pub fn set_latency(&self, id: u64, latency: Duration) {
+        self.update(id, |entry| entry.latency = Some(latency));
+    }
+
+    /// Sets the message sender of the shard. It has to be set again
+    /// if the shard has been restarted.
+    pub fn set_sender(&self, id: u64, sender: MessageSender) {
+        self.update(id, |entry| entry.sender = Some(sender));
+    }
+
+    fn sender(&self, id: u64) -> Option<MessageSender> {
+        let entries = self.entries.read().expect("shards lock poisoned");
+        entries.get(&id).and_then(|entry| entry.sender.clone())
+    }
+
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Gets the message sender of the shard serving the guild.
+    ///
+    /// It returns `None` if the shard is not running in this process.
+    #[must_use]
+    pub fn sender_for_guild(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Option<MessageSender> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+
+        self.sender((guild_id.get() >> 22) % total)
+    }
+
+    /// Forces the shard to reconnect and resume its session.
+    ///
+    /// It returns `None` if the shard is not running in this process.
+    pub fn reconnect(&self, id: u64) -> Option<Result<(), ChannelError>> {
+        self.sender(id).map(|sender| sender.close(CloseFrame::RESUME))
+    }
+
+    /// Sends the presence to all shards in this process.
+    pub fn send_presence(&self, presence: &UpdatePresence) {
+        let entries = self.entries.read().expect("shards lock poisoned");
+        for (id, entry) in entries.iter() {
+            let Some(sender) = &entry.sender else { continue };
+            if let Err(error) = sender.command(presence) {
+                tracing::warn!(?error, shard.id = %id, "Failed to send presence");
+            }
+        }
+    }
+
+    /// Reports the status of all shards.
+    ///
+    /// Shards that were healthy before are still considered ready
+    /// if they became unhealthy for no longer than `tolerance`.
+    #[must_use]
+    pub fn report(&self, tolerance: Duration) -> HealthReport {
+        let entries = self.entries.read().expect("shards lock poisoned");
+        let ready = !entries.is_empty()
+            && entries.values().all(|entry| {
+                entry.is_healthy()
+                    || entry
+                        .unhealthy_since
+                        .is_some_and(|since| since.elapsed() <= tolerance)
+            });
+
+        let shards = entries
+            .iter()
+            .map(|(id, entry)| ShardReport {
+                id: *id,
+                status: entry.status,
+                ready: entry.ready,
+                uptime_secs: entry.active_since.map(|v| v.elapsed().as_secs()),
+                latency_secs: entry.latency.map(|v| v.as_secs_f64()),
+            })
+            .collect();
+
+        HealthReport { ready, shards }
+    }
+}
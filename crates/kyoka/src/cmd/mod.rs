@@ -14,3 +14,13 @@ pub struct Ping;
     desc = "Connects the bot to the voice channel you've joined"
 )]
 pub struct Join;
+
+#[derive(Debug, CommandModel, CreateCommand)]
+#[command(
+    name = "maintenance",
+    desc = "Toggles maintenance mode of the bot (bot owners only)"
+)]
+pub struct Maintenance {
+    /// Whether maintenance mode should be enabled
+    pub enabled: bool,
+}
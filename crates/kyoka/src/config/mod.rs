@@ -1,8 +1,10 @@
 mod bot;
 mod sentry;
+mod session_limit;
 
 pub use self::bot::Bot;
 pub use self::sentry::{Sentry, SentryError};
+pub use self::session_limit::SessionLimit;
 
 use thiserror::Error;
 
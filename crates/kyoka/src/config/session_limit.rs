@@ -0,0 +1,47 @@
+use crate::util::env;
+use error_stack::{Result, ResultExt};
+use std::time::Duration;
+
+use super::LoadError;
+
+#[derive(Debug)]
+pub struct SessionLimit {
+    floor: u64,
+    refresh_interval: Duration,
+}
+
+const DEFAULT_FLOOR: u64 = 10;
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 5 * 60;
+
+impl SessionLimit {
+    #[track_caller]
+    pub fn from_env() -> Result<Self, LoadError> {
+        let floor = env::var_parse("SESSION_START_FLOOR")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_FLOOR);
+
+        let refresh_interval = env::var_parse("SESSION_START_REFRESH_INTERVAL")
+            .change_context(LoadError)?
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_SECS);
+
+        Ok(Self {
+            floor,
+            refresh_interval: Duration::from_secs(refresh_interval.max(1)),
+        })
+    }
+}
+
+impl SessionLimit {
+    /// Amount of session starts kept unused. Identifies are held
+    /// off until the limit resets once it is reached.
+    #[must_use]
+    pub const fn floor(&self) -> u64 {
+        self.floor
+    }
+
+    /// How often the session start limit is refreshed from Discord
+    #[must_use]
+    pub const fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the protocol spoken between the shards and the
+/// gateway queue server, bumped on every breaking change.
+pub const GATEWAY_QUEUE_PROTOCOL: u32 = 1;
+
+/// Header of a request to the gateway queue server which timed out,
+/// holding the ticket to ask again without losing its place.
+pub const GATEWAY_QUEUE_TICKET_HEADER: &str = "x-queue-ticket";
+
+/// Response of the gateway queue server to a handshake, which
+/// does not take any identify slot or session start.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Handshake {
+    pub protocol: u32,
+    pub application: Option<u64>,
+    pub max_concurrency: u64,
+}
@@ -1,9 +1,13 @@
+mod handshake;
 mod sensitive;
+mod session_limit;
 
 pub(crate) mod twilight;
 
 pub mod env;
+pub use handshake::*;
 pub use sensitive::*;
+pub use session_limit::*;
 
 use error_stack::{Result, ResultExt};
 use std::path::Path;
@@ -40,7 +44,11 @@ pub async fn setup_cmds(
     use crate::{cmd, perform_request};
     use twilight_interactions::command::CreateCommand;
 
-    let required_cmds = &[cmd::Ping::create_command().into()];
+    let required_cmds = &[
+        cmd::Ping::create_command().into(),
+        cmd::Join::create_command().into(),
+        cmd::Maintenance::create_command().into(),
+    ];
 
     let now = Instant::now();
     perform_request!(
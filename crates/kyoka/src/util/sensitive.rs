@@ -37,6 +37,17 @@ impl Sensitive<String> {
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    /// Compares both values without returning early, so the
+    /// secret cannot be guessed by timing the comparisons.
+    #[must_use]
+    pub fn eq_constant_time(&self, other: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), other.as_bytes());
+        if a.len() != b.len() {
+            return false;
+        }
+        a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
 }
 
 impl<T> Debug for Sensitive<T> {
@@ -165,6 +176,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_eq_constant_time() {
+        let secret = Sensitive::new(String::from("hello"));
+        assert!(secret.eq_constant_time("hello"));
+        assert!(!secret.eq_constant_time("hellp"));
+        assert!(!secret.eq_constant_time("hell"));
+        assert!(!secret.eq_constant_time(""));
+    }
+
     #[test]
     fn test_fmt() {
         let value = Sensitive::new("hello");
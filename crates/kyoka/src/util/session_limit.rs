@@ -0,0 +1,163 @@
+use error_stack::Result;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use twilight_model::gateway::SessionStartLimit;
+
+use crate::perform_request;
+
+#[derive(Debug, Error)]
+#[error("Failed to refresh session start limit")]
+pub struct RefreshSessionLimitError;
+
+#[derive(Debug)]
+struct Snapshot {
+    remaining: u64,
+    total: u64,
+    resets_at: Instant,
+}
+
+impl Snapshot {
+    fn reset_if_elapsed(&mut self) {
+        if self.resets_at <= Instant::now() {
+            self.remaining = self.total;
+        }
+    }
+}
+
+/// Session start limit of the bot as it was last seen,
+/// with `reset_after_secs` counting from now.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SessionLimitReport {
+    pub remaining: u64,
+    pub total: u64,
+    pub reset_after_secs: u64,
+}
+
+/// Tracks how many sessions the bot can still start until
+/// Discord resets its daily limit.
+///
+/// Every identify takes one session start. The tracked limit is
+/// unknown until it is updated from Discord, which allows all
+/// identifies in the meantime.
+#[derive(Debug, Clone, Default)]
+pub struct SessionLimiter {
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl SessionLimiter {
+    #[must_use]
+    pub fn new(limit: &SessionStartLimit) -> Self {
+        let limiter = Self::default();
+        limiter.update(limit);
+        limiter
+    }
+
+    pub fn update(&self, limit: &SessionStartLimit) {
+        let snapshot = Snapshot {
+            remaining: limit.remaining,
+            total: limit.total,
+            resets_at: Instant::now()
+                + Duration::from_millis(limit.reset_after),
+        };
+        *self.snapshot.lock().expect("snapshot lock poisoned") = Some(snapshot);
+    }
+
+    /// Takes one session start unless it leaves less than `floor`
+    /// session starts, returning how long to wait until the limit
+    /// resets instead.
+    pub fn acquire(&self, floor: u64) -> std::result::Result<(), Duration> {
+        let mut snapshot =
+            self.snapshot.lock().expect("snapshot lock poisoned");
+        let Some(snapshot) = snapshot.as_mut() else {
+            return Ok(());
+        };
+
+        snapshot.reset_if_elapsed();
+        if snapshot.remaining <= floor {
+            return Err(snapshot
+                .resets_at
+                .saturating_duration_since(Instant::now()));
+        }
+
+        snapshot.remaining -= 1;
+        Ok(())
+    }
+
+    /// Gives back a session start taken by [`SessionLimiter::acquire`]
+    /// which ended up unused.
+    pub fn release(&self) {
+        let mut snapshot =
+            self.snapshot.lock().expect("snapshot lock poisoned");
+
+        if let Some(snapshot) = snapshot.as_mut() {
+            snapshot.remaining = (snapshot.remaining + 1).min(snapshot.total);
+        }
+    }
+
+    #[must_use]
+    pub fn report(&self) -> Option<SessionLimitReport> {
+        let mut snapshot =
+            self.snapshot.lock().expect("snapshot lock poisoned");
+        let snapshot = snapshot.as_mut()?;
+        snapshot.reset_if_elapsed();
+
+        let reset_after =
+            snapshot.resets_at.saturating_duration_since(Instant::now());
+
+        Some(SessionLimitReport {
+            remaining: snapshot.remaining,
+            total: snapshot.total,
+            reset_after_secs: reset_after.as_secs(),
+        })
+    }
+
+    /// Updates the tracked limit with the current one from Discord.
+    pub async fn refresh(
+        &self,
+        http: &twilight_http::Client,
+    ) -> Result<SessionStartLimit, RefreshSessionLimitError> {
+        let info =
+            perform_request!(http.gateway().authed(), RefreshSessionLimitError)
+                .await?;
+
+        self.update(&info.session_start_limit);
+        Ok(info.session_start_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionLimiter;
+    use twilight_model::gateway::SessionStartLimit;
+
+    #[test]
+    fn test_acquire() {
+        let limiter = SessionLimiter::default();
+        assert!(limiter.acquire(u64::MAX).is_ok());
+
+        limiter.update(&SessionStartLimit {
+            max_concurrency: 1,
+            remaining: 3,
+            reset_after: 60_000,
+            total: 1000,
+        });
+        assert!(limiter.acquire(1).is_ok());
+        assert!(limiter.acquire(1).is_ok());
+        assert!(limiter.acquire(1).is_err());
+        assert_eq!(limiter.report().map(|v| v.remaining), Some(1));
+
+        limiter.release();
+        assert!(limiter.acquire(1).is_ok());
+
+        limiter.update(&SessionStartLimit {
+            max_concurrency: 1,
+            remaining: 0,
+            reset_after: 0,
+            total: 1000,
+        });
+        assert!(limiter.acquire(1).is_ok());
+        assert_eq!(limiter.report().map(|v| v.remaining), Some(999));
+    }
+}